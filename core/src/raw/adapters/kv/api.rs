@@ -21,6 +21,7 @@ use async_trait::async_trait;
 
 use crate::raw::*;
 use crate::Capability;
+use crate::EntryMode;
 use crate::Error;
 use crate::ErrorKind;
 use crate::Result;
@@ -50,6 +51,26 @@ pub trait Adapter: Send + Sync + Debug + Unpin + 'static {
         .with_operation("kv::Adapter::blocking_get"))
     }
 
+    /// Get the metadata of a key from service.
+    ///
+    /// - return `Ok(None)` if this key is not exist.
+    /// - the default implementation reads the whole value to get its length,
+    ///   services that keep metadata on their own could override it.
+    async fn stat(&self, path: &str) -> Result<Option<crate::Metadata>> {
+        let bs = self.get(path).await?;
+        Ok(bs.map(|bs| {
+            crate::Metadata::new(crate::EntryMode::FILE).with_content_length(bs.len() as u64)
+        }))
+    }
+
+    /// The blocking version of stat.
+    fn blocking_stat(&self, path: &str) -> Result<Option<crate::Metadata>> {
+        let bs = self.blocking_get(path)?;
+        Ok(bs.map(|bs| {
+            crate::Metadata::new(crate::EntryMode::FILE).with_content_length(bs.len() as u64)
+        }))
+    }
+
     /// Set a key into service.
     async fn set(&self, path: &str, value: &[u8]) -> Result<()>;
 
@@ -64,6 +85,23 @@ pub trait Adapter: Send + Sync + Debug + Unpin + 'static {
         .with_operation("kv::Adapter::blocking_set"))
     }
 
+    /// Set a key into service with the args of write.
+    ///
+    /// Services that could store metadata like `content_type` along with
+    /// the value should override this, the args are ignored by default.
+    async fn set_with(&self, path: &str, value: &[u8], args: &OpWrite) -> Result<()> {
+        let _ = args;
+
+        self.set(path, value).await
+    }
+
+    /// The blocking version of set_with.
+    fn blocking_set_with(&self, path: &str, value: &[u8], args: &OpWrite) -> Result<()> {
+        let _ = args;
+
+        self.blocking_set(path, value)
+    }
+
     /// Delete a key from service.
     ///
     /// - return `Ok(())` even if this key is not exist.
@@ -82,6 +120,71 @@ pub trait Adapter: Send + Sync + Debug + Unpin + 'static {
         .with_operation("kv::Adapter::blocking_delete"))
     }
 
+    /// Rename a key in service.
+    ///
+    /// - return `ErrorKind::NotFound` if `from` is not exist.
+    /// - the default implementation gets `from`, sets it to `to` and then
+    ///   deletes `from`, services that could move keys natively or
+    ///   atomically could override it.
+    async fn rename(&self, from: &str, to: &str) -> Result<()> {
+        let bs = match self.get(from).await? {
+            Some(bs) => bs,
+            None => return Err(Error::new(ErrorKind::NotFound, "kv doesn't have this path")),
+        };
+
+        self.set(to, &bs).await?;
+        self.delete(from).await
+    }
+
+    /// The blocking version of rename.
+    fn blocking_rename(&self, from: &str, to: &str) -> Result<()> {
+        let bs = match self.blocking_get(from)? {
+            Some(bs) => bs,
+            None => return Err(Error::new(ErrorKind::NotFound, "kv doesn't have this path")),
+        };
+
+        self.blocking_set(to, &bs)?;
+        self.blocking_delete(from)
+    }
+
+    /// Copy a key in service.
+    ///
+    /// - return `ErrorKind::NotFound` if `from` is not exist.
+    /// - the default implementation gets `from` and sets it to `to`,
+    ///   services that store more than the value with keys could override
+    ///   it to copy them as well.
+    async fn copy(&self, from: &str, to: &str) -> Result<()> {
+        let bs = match self.get(from).await? {
+            Some(bs) => bs,
+            None => return Err(Error::new(ErrorKind::NotFound, "kv doesn't have this path")),
+        };
+
+        self.set(to, &bs).await
+    }
+
+    /// The blocking version of copy.
+    fn blocking_copy(&self, from: &str, to: &str) -> Result<()> {
+        let bs = match self.blocking_get(from)? {
+            Some(bs) => bs,
+            None => return Err(Error::new(ErrorKind::NotFound, "kv doesn't have this path")),
+        };
+
+        self.blocking_set(to, &bs)
+    }
+
+    /// Delete keys from service in one batch.
+    ///
+    /// - return `Ok(())` even if some keys are not exist.
+    async fn batch_delete(&self, paths: &[String]) -> Result<()> {
+        let _ = paths;
+
+        Err(Error::new(
+            ErrorKind::Unsupported,
+            "kv adapter doesn't support this operation",
+        )
+        .with_operation("kv::Adapter::batch_delete"))
+    }
+
     /// Scan a key prefix to get all keys that start with this key.
     async fn scan(&self, path: &str) -> Result<Vec<String>> {
         let _ = path;
@@ -105,6 +208,30 @@ pub trait Adapter: Send + Sync + Debug + Unpin + 'static {
         .with_operation("kv::Adapter::blocking_scan"))
     }
 
+    /// Scan a key prefix to get all keys that start with this key together
+    /// with their metadata.
+    ///
+    /// - the default implementation calls `scan` and only fills the mode,
+    ///   services that get the metadata of keys along with scanning could
+    ///   override it to save the stat of every entry.
+    async fn scan_entries(&self, path: &str) -> Result<Vec<(String, crate::Metadata)>> {
+        Ok(self
+            .scan(path)
+            .await?
+            .into_iter()
+            .map(entry_of_key)
+            .collect())
+    }
+
+    /// The blocking version of scan_entries.
+    fn blocking_scan_entries(&self, path: &str) -> Result<Vec<(String, crate::Metadata)>> {
+        Ok(self
+            .blocking_scan(path)?
+            .into_iter()
+            .map(entry_of_key)
+            .collect())
+    }
+
     /// Append a key into service
     async fn append(&self, path: &str, value: &[u8]) -> Result<()> {
         let _ = path;
@@ -131,6 +258,16 @@ pub trait Adapter: Send + Sync + Debug + Unpin + 'static {
     }
 }
 
+/// Build the entry of a scanned key, keys ending with `/` are dirs.
+fn entry_of_key(key: String) -> (String, crate::Metadata) {
+    let mode = if key.ends_with('/') {
+        EntryMode::DIR
+    } else {
+        EntryMode::FILE
+    };
+    (key, crate::Metadata::new(mode))
+}
+
 /// Metadata for this key value accessor.
 pub struct Metadata {
     scheme: Scheme,
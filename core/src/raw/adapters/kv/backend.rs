@@ -60,6 +60,11 @@ where
         self.root = normalize_root(root);
         self
     }
+
+    /// Get the underlying kv adapter of this backend.
+    pub fn adapter(&self) -> &S {
+        &self.kv
+    }
 }
 
 #[async_trait]
@@ -122,6 +127,12 @@ impl<S: Adapter> Accessor for Backend<S> {
     async fn read(&self, path: &str, args: OpRead) -> Result<(RpRead, Self::Reader)> {
         let p = build_abs_path(&self.root, path);
 
+        if self.should_check_if_none_match(&args) {
+            if let Some(meta) = self.kv.stat(&p).await? {
+                check_if_none_match(&meta, &args)?;
+            }
+        }
+
         let bs = match self.kv.get(&p).await? {
             Some(bs) => bs,
             None => return Err(Error::new(ErrorKind::NotFound, "kv doesn't have this path")),
@@ -136,6 +147,12 @@ impl<S: Adapter> Accessor for Backend<S> {
     fn blocking_read(&self, path: &str, args: OpRead) -> Result<(RpRead, Self::BlockingReader)> {
         let p = build_abs_path(&self.root, path);
 
+        if self.should_check_if_none_match(&args) {
+            if let Some(meta) = self.kv.blocking_stat(&p)? {
+                check_if_none_match(&meta, &args)?;
+            }
+        }
+
         let bs = match self.kv.blocking_get(&p)? {
             Some(bs) => bs,
             None => return Err(Error::new(ErrorKind::NotFound, "kv doesn't have this path")),
@@ -145,16 +162,16 @@ impl<S: Adapter> Accessor for Backend<S> {
         Ok((RpRead::new(bs.len() as u64), oio::Cursor::from(bs)))
     }
 
-    async fn write(&self, path: &str, _: OpWrite) -> Result<(RpWrite, Self::Writer)> {
+    async fn write(&self, path: &str, args: OpWrite) -> Result<(RpWrite, Self::Writer)> {
         let p = build_abs_path(&self.root, path);
 
-        Ok((RpWrite::new(), KvWriter::new(self.kv.clone(), p)))
+        Ok((RpWrite::new(), KvWriter::new(self.kv.clone(), p, args)))
     }
 
-    fn blocking_write(&self, path: &str, _: OpWrite) -> Result<(RpWrite, Self::BlockingWriter)> {
+    fn blocking_write(&self, path: &str, args: OpWrite) -> Result<(RpWrite, Self::BlockingWriter)> {
         let p = build_abs_path(&self.root, path);
 
-        Ok((RpWrite::new(), KvWriter::new(self.kv.clone(), p)))
+        Ok((RpWrite::new(), KvWriter::new(self.kv.clone(), p, args)))
     }
 
     async fn stat(&self, path: &str, _: OpStat) -> Result<RpStat> {
@@ -163,11 +180,8 @@ impl<S: Adapter> Accessor for Backend<S> {
         if p.is_empty() || p.ends_with('/') {
             Ok(RpStat::new(Metadata::new(EntryMode::DIR)))
         } else {
-            let bs = self.kv.get(&p).await?;
-            match bs {
-                Some(bs) => Ok(RpStat::new(
-                    Metadata::new(EntryMode::FILE).with_content_length(bs.len() as u64),
-                )),
+            match self.kv.stat(&p).await? {
+                Some(meta) => Ok(RpStat::new(meta)),
                 None => Err(Error::new(ErrorKind::NotFound, "kv doesn't have this path")),
             }
         }
@@ -179,11 +193,8 @@ impl<S: Adapter> Accessor for Backend<S> {
         if p.is_empty() || p.ends_with('/') {
             Ok(RpStat::new(Metadata::new(EntryMode::DIR)))
         } else {
-            let bs = self.kv.blocking_get(&p)?;
-            match bs {
-                Some(bs) => Ok(RpStat::new(
-                    Metadata::new(EntryMode::FILE).with_content_length(bs.len() as u64),
-                )),
+            match self.kv.blocking_stat(&p)? {
+                Some(meta) => Ok(RpStat::new(meta)),
                 None => Err(Error::new(ErrorKind::NotFound, "kv doesn't have this path")),
             }
         }
@@ -196,6 +207,23 @@ impl<S: Adapter> Accessor for Backend<S> {
         Ok(RpDelete::default())
     }
 
+    async fn batch(&self, args: OpBatch) -> Result<RpBatch> {
+        let ops = args.into_operation();
+        let paths: Vec<String> = ops
+            .iter()
+            .map(|(path, op)| match op {
+                BatchOperation::Delete(_) => build_abs_path(&self.root, path),
+            })
+            .collect();
+
+        self.kv.batch_delete(&paths).await?;
+        let results = ops
+            .into_iter()
+            .map(|(path, _)| (path, Ok(RpDelete::default().into())))
+            .collect();
+        Ok(RpBatch::new(results))
+    }
+
     fn blocking_delete(&self, path: &str, _: OpDelete) -> Result<RpDelete> {
         let p = build_abs_path(&self.root, path);
 
@@ -212,7 +240,7 @@ impl<S: Adapter> Accessor for Backend<S> {
         }
 
         let p = build_abs_path(&self.root, path);
-        let res = self.kv.scan(&p).await?;
+        let res = self.kv.scan_entries(&p).await?;
         let pager = KvPager::new(&self.root, res);
 
         Ok((RpList::default(), pager))
@@ -227,7 +255,7 @@ impl<S: Adapter> Accessor for Backend<S> {
         }
 
         let p = build_abs_path(&self.root, path);
-        let res = self.kv.blocking_scan(&p)?;
+        let res = self.kv.blocking_scan_entries(&p)?;
         let pager = KvPager::new(&self.root, res);
 
         Ok((RpList::default(), pager))
@@ -237,15 +265,7 @@ impl<S: Adapter> Accessor for Backend<S> {
         let from = build_abs_path(&self.root, from);
         let to = build_abs_path(&self.root, to);
 
-        let bs = match self.kv.get(&from).await? {
-            // TODO: we can reuse the metadata in value to build content range.
-            Some(bs) => bs,
-            None => return Err(Error::new(ErrorKind::NotFound, "kv doesn't have this path")),
-        };
-
-        self.kv.set(&to, &bs).await?;
-
-        self.kv.delete(&from).await?;
+        self.kv.rename(&from, &to).await?;
         Ok(RpRename::default())
     }
 
@@ -253,15 +273,7 @@ impl<S: Adapter> Accessor for Backend<S> {
         let from = build_abs_path(&self.root, from);
         let to = build_abs_path(&self.root, to);
 
-        let bs = match self.kv.blocking_get(&from)? {
-            // TODO: we can reuse the metadata in value to build content range.
-            Some(bs) => bs,
-            None => return Err(Error::new(ErrorKind::NotFound, "kv doesn't have this path")),
-        };
-
-        self.kv.blocking_set(&to, &bs)?;
-
-        self.kv.blocking_delete(&from)?;
+        self.kv.blocking_rename(&from, &to)?;
         Ok(RpRename::default())
     }
 
@@ -269,14 +281,7 @@ impl<S: Adapter> Accessor for Backend<S> {
         let from = build_abs_path(&self.root, from);
         let to = build_abs_path(&self.root, to);
 
-        let bs = match self.kv.get(&from).await? {
-            // TODO: we can reuse the metadata in value to build content range.
-            Some(bs) => bs,
-            None => return Err(Error::new(ErrorKind::NotFound, "kv doesn't have this path")),
-        };
-
-        self.kv.set(&to, &bs).await?;
-
+        self.kv.copy(&from, &to).await?;
         Ok(RpCopy::default())
     }
 
@@ -284,14 +289,7 @@ impl<S: Adapter> Accessor for Backend<S> {
         let from = build_abs_path(&self.root, from);
         let to = build_abs_path(&self.root, to);
 
-        let bs = match self.kv.blocking_get(&from)? {
-            // TODO: we can reuse the metadata in value to build content range.
-            Some(bs) => bs,
-            None => return Err(Error::new(ErrorKind::NotFound, "kv doesn't have this path")),
-        };
-
-        self.kv.blocking_set(&to, &bs)?;
-
+        self.kv.blocking_copy(&from, &to)?;
         Ok(RpCopy::default())
     }
 }
@@ -314,15 +312,33 @@ where
             (None, None) => bs,
         }
     }
+
+    /// Conditional reads are only checked if the adapter provides etags.
+    fn should_check_if_none_match(&self, args: &OpRead) -> bool {
+        args.if_none_match().is_some() && self.kv.metadata().capabilities().read_with_if_none_match
+    }
+}
+
+/// Return `ConditionNotMatch` if the etag of value matches `if_none_match`,
+/// so that the unchanged value is not transferred again.
+fn check_if_none_match(meta: &Metadata, args: &OpRead) -> Result<()> {
+    match (meta.etag(), args.if_none_match()) {
+        (Some(etag), Some(if_none_match)) if etag == if_none_match => Err(Error::new(
+            ErrorKind::ConditionNotMatch,
+            "kv value is not modified since given etag",
+        )
+        .with_context("etag", etag)),
+        _ => Ok(()),
+    }
 }
 
 pub struct KvPager {
     root: String,
-    inner: Option<Vec<String>>,
+    inner: Option<Vec<(String, Metadata)>>,
 }
 
 impl KvPager {
-    fn new(root: &str, inner: Vec<String>) -> Self {
+    fn new(root: &str, inner: Vec<(String, Metadata)>) -> Self {
         Self {
             root: root.to_string(),
             inner: Some(inner),
@@ -334,15 +350,7 @@ impl KvPager {
             .inner
             .take()?
             .into_iter()
-            .map(|v| {
-                let mode = if v.ends_with('/') {
-                    EntryMode::DIR
-                } else {
-                    EntryMode::FILE
-                };
-
-                oio::Entry::new(&build_rel_path(&self.root, &v), Metadata::new(mode))
-            })
+            .map(|(v, meta)| oio::Entry::new(&build_rel_path(&self.root, &v), meta))
             .collect();
 
         Some(res)
@@ -365,16 +373,18 @@ impl oio::BlockingPage for KvPager {
 pub struct KvWriter<S> {
     kv: Arc<S>,
     path: String,
+    args: OpWrite,
 
     buffer: Buffer,
     future: Option<BoxFuture<'static, Result<()>>>,
 }
 
 impl<S> KvWriter<S> {
-    fn new(kv: Arc<S>, path: String) -> Self {
+    fn new(kv: Arc<S>, path: String, args: OpWrite) -> Self {
         KvWriter {
             kv,
             path,
+            args,
             buffer: Buffer::Active(BytesMut::new()),
             future: None,
         }
@@ -422,6 +432,7 @@ impl<S: Adapter> oio::Write for KvWriter<S> {
                 None => {
                     let kv = self.kv.clone();
                     let path = self.path.clone();
+                    let args = self.args.clone();
                     let buf = match &mut self.buffer {
                         Buffer::Active(buf) => {
                             let buf = buf.split().freeze();
@@ -431,7 +442,7 @@ impl<S: Adapter> oio::Write for KvWriter<S> {
                         Buffer::Frozen(buf) => buf.clone(),
                     };
 
-                    let fut = async move { kv.set(&path, &buf).await };
+                    let fut = async move { kv.set_with(&path, &buf, &args).await };
                     self.future = Some(Box::pin(fut));
                 }
             }
@@ -473,7 +484,7 @@ impl<S: Adapter> oio::BlockingWrite for KvWriter<S> {
             Buffer::Frozen(buf) => buf.clone(),
         };
 
-        self.kv.blocking_set(&self.path, &buf)?;
+        self.kv.blocking_set_with(&self.path, &buf, &self.args)?;
         Ok(())
     }
 }
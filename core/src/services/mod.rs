@@ -228,3 +228,29 @@ pub use self::sqlite::Sqlite;
 mod zookeeper;
 #[cfg(feature = "services-zookeeper")]
 pub use self::zookeeper::Zookeeper;
+#[cfg(feature = "services-zookeeper")]
+pub use self::zookeeper::ZookeeperAdapter;
+#[cfg(feature = "services-zookeeper")]
+pub use self::zookeeper::ZookeeperBatchOp;
+#[cfg(feature = "services-zookeeper")]
+pub use self::zookeeper::ZookeeperCreateMode;
+#[cfg(feature = "services-zookeeper-encryption")]
+pub use self::zookeeper::ZookeeperDecryptError;
+#[cfg(feature = "services-zookeeper")]
+pub use self::zookeeper::ZookeeperDurability;
+#[cfg(feature = "services-zookeeper")]
+pub use self::zookeeper::ZookeeperFsckReport;
+#[cfg(feature = "services-zookeeper")]
+pub use self::zookeeper::ZookeeperMapEvent;
+#[cfg(feature = "services-zookeeper")]
+pub use self::zookeeper::ZookeeperSession;
+#[cfg(feature = "services-zookeeper")]
+pub use self::zookeeper::ZookeeperStats;
+#[cfg(feature = "services-zookeeper")]
+pub use self::zookeeper::ZookeeperSubtreeReport;
+#[cfg(feature = "services-zookeeper")]
+pub use self::zookeeper::ZookeeperTransformer;
+#[cfg(feature = "services-zookeeper")]
+pub use self::zookeeper::ZookeeperTreeDiff;
+#[cfg(feature = "services-zookeeper")]
+pub use self::zookeeper::ZookeeperWatchEvent;
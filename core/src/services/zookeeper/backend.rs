@@ -15,15 +15,52 @@
 // specific language governing permissions and limitations
 // under the License.
 
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
 use std::collections::HashMap;
+use std::collections::HashSet;
+use std::future::Future;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
 use zookeeper_client as zk;
 
 use crate::raw::adapters::kv;
 use crate::Scheme;
 use async_trait::async_trait;
-use tokio::sync::OnceCell;
+#[cfg(feature = "services-zookeeper-encryption")]
+use base64::prelude::BASE64_STANDARD;
+#[cfg(feature = "services-zookeeper-encryption")]
+use base64::Engine;
+use chrono::DateTime;
+use chrono::TimeZone;
+use chrono::Utc;
+use futures::stream;
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use futures::TryStreamExt;
+use parking_lot::Mutex;
 
-use crate::raw::build_rooted_abs_path;
+use super::cache::ReadCache;
+use super::error::parse_multi_write_error;
+use super::error::parse_zookeeper_error;
+use super::header::ValueHeader;
+use super::proxy::ProxyTunnel;
+use super::proxy::ZookeeperProxy;
+use super::stats::Stats;
+use super::stats::ZookeeperStats;
+use super::trace;
+#[cfg(feature = "services-zookeeper-encryption")]
+use super::transform::AesGcmTransformer;
+use super::transform::ZookeeperTransformer;
+use super::watch::KeyWatcher;
+use super::watch::MapWatcher;
+use super::watch::ZookeeperMapEvent;
+use super::watch::ZookeeperWatchEvent;
+use crate::raw::BytesRange;
+use crate::raw::OpWrite;
 use crate::Builder;
 use crate::Error;
 use crate::ErrorKind;
@@ -32,14 +69,39 @@ use crate::*;
 use std::fmt::Debug;
 use std::fmt::Formatter;
 
-use substring::Substring;
-
+use log::debug;
 use log::warn;
 
 const DEFAULT_ZOOKEEPER_ENDPOINT: &str = "127.0.0.1:2181";
 /// The scheme for zookeeper authentication
 /// currently we do not support sasl authentication
 const ZOOKEEPER_AUTH_SCHEME: &str = "digest";
+/// The max number of nodes known to exist to keep.
+const KNOWN_NODES_CAPACITY: usize = 4096;
+/// The timeout to check whether an endpoint is reachable
+const ENDPOINT_CHECK_TIMEOUT: Duration = Duration::from_secs(3);
+/// The max number of retries of swap on concurrent modification
+const SWAP_MAX_RETRIES: usize = 3;
+
+/// The default timeout of establishing a session.
+const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The default max size of a node, which is the default `jute.maxbuffer`
+/// of zookeeper server.
+const DEFAULT_MAX_NODE_SIZE: usize = 1024 * 1024;
+
+/// The default `tickTime` of zookeeper server, which bounds the session
+/// timeout to `[2, 20]` ticks.
+const DEFAULT_ZOOKEEPER_TICK_TIME: Duration = Duration::from_secs(2);
+/// The target of all log messages from zookeeper service
+pub(super) static LOGGING_TARGET: &str = "opendal::services::zookeeper";
+/// The max number of in-flight stat requests while stating many nodes
+const STAT_CONCURRENCY: usize = 16;
+/// How long a write is considered recent for `read_your_writes`
+const RECENT_WRITE_WINDOW: Duration = Duration::from_secs(10);
+
+/// The subtree reserved by zookeeper server for its own use.
+const ZOOKEEPER_RESERVED_PATH: &str = "/zookeeper";
 
 /// Zookeeper backend builder
 #[derive(Clone, Default)]
@@ -47,14 +109,89 @@ pub struct ZookeeperBuilder {
     /// network address of the Zookeeper service
     /// Default: 127.0.0.1:2181
     endpoint: Option<String>,
+    /// the root under which all paths are resolved, default /
+    root: Option<String>,
     /// the user to connect to zookeeper service, default None
     username: Option<String>,
-    /// the password file of the user to connect to zookeeper service, default None
+    /// the password of the user to connect to zookeeper service, default None
     password: Option<String>,
+    /// the file to read the password of the user from, default None
+    password_file: Option<String>,
+    /// the auth scheme of username and password, only digest is supported, default digest
+    auth_scheme: Option<String>,
+    /// extra `(scheme, credential)` pairs to add to the session, default empty
+    auths: Vec<(String, String)>,
+    /// `(scheme, id, permissions)` triples of the acl to create nodes with, default empty
+    acls: Vec<(String, String, String)>,
+    /// verify that the credentials have taken effect after auth, default false
+    verify_auth: bool,
+    /// fallback to an anonymous session if auth failed, default false
+    fallback_anonymous_reads: bool,
+    /// acknowledge that no credentials are set on purpose, default false
+    allow_anonymous: bool,
+    /// sync and retry once if our own recent write is not visible, default false
+    read_your_writes: bool,
+    /// warn if a create pushes the children of parent above this, default None
+    max_children_warn: Option<usize>,
+    /// reject a create pushing the children of parent above this, default None
+    max_children_error: Option<usize>,
+    /// the max number of entries in read cache, default None
+    cache_capacity: Option<usize>,
+    /// the max total bytes of values in read cache, default None
+    cache_max_bytes: Option<usize>,
+    /// the time to live of entries in read cache, default None
+    cache_ttl: Option<Duration>,
+    /// the number of connections to zookeeper, default 1
+    pool_size: Option<usize>,
+    /// the max bytes of a value to write, default 1MiB
+    max_node_size: Option<usize>,
+    /// the session timeout to negotiate with zookeeper, default None
+    session_timeout: Option<Duration>,
+    /// the timeout of establishing a session, default 10s
+    connect_timeout: Option<Duration>,
+    /// the acknowledgment level of writes, default QuorumAck
+    durability: ZookeeperDurability,
+    /// the mode to create nodes with on set, default Persistent
+    create_mode: ZookeeperCreateMode,
+    /// the label to prefix log messages with, default None
+    label: Option<String>,
+    /// return not found when listing a missing node, default false
+    strict_list: bool,
+    /// only advertise read and list capabilities, default false
+    read_only: bool,
+    /// store content type and other metadata along with values, default false
+    store_metadata: bool,
+    /// delete the subtree of a node with children, default false
+    recursive_delete: bool,
+    /// require all endpoints to be reachable on connect, default false
+    require_all_endpoints: bool,
+    /// the proxy to tunnel connections through, default None
+    proxy: Option<String>,
+    /// the transformers applied to values in order, default empty
+    transformers: Vec<Arc<dyn ZookeeperTransformer>>,
+    /// the key to encrypt values with AES-256-GCM, default None
+    #[cfg(feature = "services-zookeeper-encryption")]
+    encryption_key: Option<Vec<u8>>,
+    /// read values without the encryption header as plaintext, default false
+    #[cfg(feature = "services-zookeeper-encryption")]
+    encryption_allow_plaintext: bool,
+    /// `(key, reason)` of the config values failed to parse in `from_map`,
+    /// returned by `build`, default empty
+    invalid_config: Vec<(String, String)>,
 }
 
 impl ZookeeperBuilder {
     /// Set the endpoint of zookeeper service
+    ///
+    /// The endpoint could be either an ip address or a hostname. Hostnames
+    /// are kept as is and resolved again every time the client (re)connects,
+    /// so changes in DNS records will be followed without pinning a stale ip.
+    ///
+    /// Multiple servers of an ensemble could be given as a comma separated
+    /// list like `host1:2181,host2:2181`, the client connects to any of them
+    /// and fails over to the others. Every server must be in `host:port`
+    /// form, malformed ones are rejected with `ErrorKind::ConfigInvalid`
+    /// by `build`.
     pub fn endpoint(&mut self, endpoint: &str) -> &mut Self {
         if !endpoint.is_empty() {
             self.endpoint = Some(endpoint.to_string());
@@ -62,6 +199,35 @@ impl ZookeeperBuilder {
         self
     }
 
+    /// Set the servers of the zookeeper ensemble to connect to.
+    ///
+    /// This is the same as setting a comma separated list by
+    /// [`ZookeeperBuilder::endpoint`], empty entries are ignored.
+    pub fn endpoints(&mut self, endpoints: Vec<String>) -> &mut Self {
+        let endpoints: Vec<&str> = endpoints
+            .iter()
+            .map(|v| v.trim())
+            .filter(|v| !v.is_empty())
+            .collect();
+        self.endpoint(&endpoints.join(","))
+    }
+
+    /// Set the root of this backend.
+    ///
+    /// All operations will happen under this root, so that multiple
+    /// backends can be namespaced in the same ensemble, for example
+    /// `/myapp/opendal`. The root itself is created along with other
+    /// missing ancestors on the first write. Helpers on `ZkAdapter` take
+    /// absolute paths and are not affected by the root.
+    ///
+    /// Default to `/`.
+    pub fn root(&mut self, root: &str) -> &mut Self {
+        if !root.is_empty() {
+            self.root = Some(root.to_string());
+        }
+        self
+    }
+
     /// Set the username of zookeeper service
     pub fn username(&mut self, username: &str) -> &mut Self {
         if !username.is_empty() {
@@ -77,6 +243,415 @@ impl ZookeeperBuilder {
         }
         self
     }
+
+    /// Specify the file to read the password of zookeeper service from.
+    ///
+    /// The file is read at build time with trailing newlines trimmed, which
+    /// keeps the secret out of command lines and config files. It conflicts
+    /// with [`ZookeeperBuilder::password`].
+    pub fn password_file(&mut self, path: &str) -> &mut Self {
+        if !path.is_empty() {
+            self.password_file = Some(path.to_string());
+        }
+        self
+    }
+
+    /// Set the auth scheme of the `username`/`password` pair.
+    ///
+    /// Only `digest` is supported. Other schemes, `sasl` in particular, are
+    /// rejected at build time since the zookeeper client has no SASL
+    /// handshake. Credentials of other schemes can be added with `auth`.
+    ///
+    /// Default to `digest`.
+    pub fn auth_scheme(&mut self, scheme: &str) -> &mut Self {
+        if !scheme.is_empty() {
+            self.auth_scheme = Some(scheme.to_string());
+        }
+        self
+    }
+
+    /// Add an extra auth credential for the given scheme to the session.
+    ///
+    /// ZooKeeper allows a session to carry multiple identities, this is
+    /// useful when the ACLs require matching more than one of them. All
+    /// credentials are applied in order after connecting, and after the
+    /// `username`/`password` pair if it's set.
+    ///
+    /// For example: `auth("digest", "user:password")`
+    pub fn auth(&mut self, scheme: &str, credential: &str) -> &mut Self {
+        self.auths
+            .push((scheme.to_string(), credential.to_string()));
+        self
+    }
+
+    /// Add extra `(scheme, credential)` auth entries to the session, the
+    /// same as calling [`ZookeeperBuilder::auth`] for each of them.
+    ///
+    /// A session authenticated only by these entries without `username`
+    /// creates nodes with `creator_all` ACL as well, granting all of the
+    /// identities.
+    pub fn auths(&mut self, auths: Vec<(String, String)>) -> &mut Self {
+        for (scheme, credential) in auths {
+            self.auth(&scheme, &credential);
+        }
+        self
+    }
+
+    /// Add an entry to the ACL of created nodes.
+    ///
+    /// `permissions` is a combination of `r` (read), `w` (write), `c`
+    /// (create), `d` (delete) and `a` (admin), or `all`. Unknown permissions
+    /// return `ErrorKind::ConfigInvalid` at build time.
+    ///
+    /// For example, to keep writes restricted to the current user while
+    /// granting world read access:
+    /// `acl("auth", "", "all").acl("world", "anyone", "r")`
+    ///
+    /// Default to `creator_all` if credentials are set, `anyone_all`
+    /// otherwise.
+    pub fn acl(&mut self, scheme: &str, id: &str, permissions: &str) -> &mut Self {
+        self.acls
+            .push((scheme.to_string(), id.to_string(), permissions.to_string()));
+        self
+    }
+
+    /// Verify that the credentials have actually taken effect after auth.
+    ///
+    /// Some servers accept the `auth` call even if they don't have any auth
+    /// configured, leaving the ACLs granted to an unverified id. With this
+    /// option enabled, opendal creates a temporary ephemeral node under `root`
+    /// right after connecting and checks that its ACL is owned by one of the
+    /// configured identities, returning `ErrorKind::ConfigInvalid` otherwise.
+    /// Both `username`/`password` and credentials added by `auth` are
+    /// checked, digest ids must match the user of the credential.
+    ///
+    /// This is disabled by default since it writes to the server.
+    pub fn verify_auth(&mut self) -> &mut Self {
+        self.verify_auth = true;
+        self
+    }
+
+    /// Fallback to an anonymous session if auth failed.
+    ///
+    /// This is useful when auth is only required for writes, so read-only
+    /// consumers can keep reading world-readable nodes during a credential
+    /// outage. A warning will be logged on fallback, and writes will fail
+    /// with permission denied since the session doesn't carry any identity.
+    ///
+    /// This is disabled by default to avoid masking auth problems.
+    pub fn fallback_anonymous_reads(&mut self) -> &mut Self {
+        self.fallback_anonymous_reads = true;
+        self
+    }
+
+    /// Acknowledge that no credentials are set on purpose.
+    ///
+    /// Without credentials, nodes are created with the `anyone` ACL and a
+    /// warning is logged on every build since it's usually a misconfiguration.
+    /// With this option enabled, the message is demoted to `debug` so that
+    /// intentionally anonymous setups like local development don't pollute
+    /// the logs. It has no effect if any credential is set.
+    pub fn allow_anonymous(&mut self) -> &mut Self {
+        self.allow_anonymous = true;
+        self
+    }
+
+    /// Sync and retry once if a node written recently through this backend
+    /// is missing on read.
+    ///
+    /// Reads may be served by a follower lagging behind the leader. ZooKeeper
+    /// only guarantees that a session sees its own writes, so with multiple
+    /// connections (see [`ZookeeperBuilder::pool_size`]) or after reconnecting
+    /// to another server, a `get` right after a `set` could see `NoNode`.
+    /// With this option enabled, such a `NoNode` on a path written by this
+    /// backend in the last 10 seconds issues a `sync` to let the server catch
+    /// up with the leader, and then reads again before returning not found.
+    ///
+    /// Only our own recent writes are covered, nodes written by other clients
+    /// are read as is. The retry adds one extra round trip plus the sync
+    /// latency to such reads, other reads are not affected.
+    pub fn read_your_writes(&mut self) -> &mut Self {
+        self.read_your_writes = true;
+        self
+    }
+
+    /// Log a warning if creating a node would push the number of children
+    /// of its parent above `n`.
+    ///
+    /// ZooKeeper performance degrades when a node has too many children, and
+    /// listing it may exceed the max packet size. This is a guardrail to
+    /// catch fan-out explosions early. When enabled, the parent is stated
+    /// before each node creation to read `num_children`, which costs one
+    /// extra round trip. Updating existing nodes is not affected.
+    pub fn max_children_warn(&mut self, n: usize) -> &mut Self {
+        self.max_children_warn = Some(n);
+        self
+    }
+
+    /// Reject creating a node if it would push the number of children of its
+    /// parent above `n`.
+    ///
+    /// Refer to [`ZookeeperBuilder::max_children_warn`] for the cost.
+    pub fn max_children_error(&mut self, n: usize) -> &mut Self {
+        self.max_children_error = Some(n);
+        self
+    }
+
+    /// Set the max number of entries kept in the read cache.
+    ///
+    /// The read cache is enabled once any of `cache_capacity`,
+    /// `cache_max_bytes` or `cache_ttl` is set. Values read by `get` are
+    /// cached in process, and the least recently used entries are evicted
+    /// when the cache is full.
+    ///
+    /// Entries are invalidated by `set` and `delete` issued through this
+    /// backend. Changes made by other clients are not observed until the
+    /// entry is evicted or expired, so use `cache_ttl` to bound staleness.
+    pub fn cache_capacity(&mut self, entries: usize) -> &mut Self {
+        if entries > 0 {
+            self.cache_capacity = Some(entries);
+        }
+        self
+    }
+
+    /// Set the max total bytes of values kept in the read cache.
+    ///
+    /// Values larger than this limit are never cached. Refer to
+    /// [`ZookeeperBuilder::cache_capacity`] for the cache behavior.
+    pub fn cache_max_bytes(&mut self, bytes: usize) -> &mut Self {
+        if bytes > 0 {
+            self.cache_max_bytes = Some(bytes);
+        }
+        self
+    }
+
+    /// Set the time to live of entries in the read cache.
+    ///
+    /// Refer to [`ZookeeperBuilder::cache_capacity`] for the cache behavior.
+    pub fn cache_ttl(&mut self, ttl: Duration) -> &mut Self {
+        if !ttl.is_zero() {
+            self.cache_ttl = Some(ttl);
+        }
+        self
+    }
+
+    /// Set the session timeout to negotiate with zookeeper service.
+    ///
+    /// A short timeout detects failures fast but may expire sessions on
+    /// network blips, while a long one keeps ephemeral nodes alive for a
+    /// while after the client is gone. The server clamps the timeout to
+    /// between 2 and 20 times of its `tickTime`, so a value out of this range
+    /// for the default `tickTime` of 2s (4s to 40s) returns
+    /// `ErrorKind::ConfigInvalid` at build time.
+    ///
+    /// Default to the client default of 6s.
+    pub fn session_timeout(&mut self, timeout: Duration) -> &mut Self {
+        if !timeout.is_zero() {
+            self.session_timeout = Some(timeout);
+        }
+        self
+    }
+
+    /// Set the timeout of establishing a session with zookeeper service.
+    ///
+    /// Connecting to an unreachable ensemble could hang for a long time, the
+    /// connect is abandoned after this timeout with a temporary
+    /// `ErrorKind::Unexpected` error. It's independent from the
+    /// [`ZookeeperBuilder::session_timeout`] negotiated after connecting.
+    ///
+    /// Default to 10s.
+    pub fn connect_timeout(&mut self, timeout: Duration) -> &mut Self {
+        if !timeout.is_zero() {
+            self.connect_timeout = Some(timeout);
+        }
+        self
+    }
+
+    /// Set the max bytes of a value to write.
+    ///
+    /// ZooKeeper rejects requests larger than its `jute.maxbuffer` with an
+    /// opaque connection error, so writes of larger values are rejected
+    /// before being sent with an error telling the size. The limit applies
+    /// to the value encoded by transformers. Set it to match the server if
+    /// `jute.maxbuffer` has been changed.
+    ///
+    /// Default to 1MiB.
+    pub fn max_node_size(&mut self, bytes: usize) -> &mut Self {
+        if bytes > 0 {
+            self.max_node_size = Some(bytes);
+        }
+        self
+    }
+
+    /// Set the number of connections to zookeeper service.
+    ///
+    /// A single zookeeper client multiplexes all requests over one
+    /// connection, which could be a bottleneck for throughput-bound
+    /// workloads. With a pool, operations are dispatched across connections
+    /// in round-robin.
+    ///
+    /// Every connection holds its own session, so:
+    ///
+    /// - The server needs to maintain `pool_size` sessions for this backend.
+    /// - Ephemeral nodes and watches belong to the connection the operation
+    ///   is dispatched to.
+    /// - Read-after-write is only guaranteed within the same connection.
+    ///
+    /// Default to 1.
+    pub fn pool_size(&mut self, size: usize) -> &mut Self {
+        if size > 0 {
+            self.pool_size = Some(size);
+        }
+        self
+    }
+
+    /// Set the acknowledgment level of writes.
+    ///
+    /// Refer to [`ZookeeperDurability`] for the semantics of each level.
+    ///
+    /// Default to [`ZookeeperDurability::QuorumAck`].
+    pub fn durability(&mut self, durability: ZookeeperDurability) -> &mut Self {
+        self.durability = durability;
+        self
+    }
+
+    /// Set the mode to create missing nodes with on write.
+    ///
+    /// Only the written node uses this mode, missing ancestors are always
+    /// created as persistent since zookeeper doesn't allow children under
+    /// ephemeral nodes.
+    ///
+    /// Default to [`ZookeeperCreateMode::Persistent`].
+    pub fn create_mode(&mut self, mode: ZookeeperCreateMode) -> &mut Self {
+        self.create_mode = mode;
+        self
+    }
+
+    /// Return `ErrorKind::NotFound` when listing a node that doesn't exist.
+    ///
+    /// An existing node without children is always listed as empty. By
+    /// default a missing node is listed as empty as well, matching most of
+    /// other services. With this option enabled, listing a missing node
+    /// returns `ErrorKind::NotFound` instead.
+    pub fn strict_list(&mut self) -> &mut Self {
+        self.strict_list = true;
+        self
+    }
+
+    /// Only allow reads and lists through this backend.
+    ///
+    /// This is useful for credentials granted read ACLs only. Writes,
+    /// deletes and renames are rejected by opendal with
+    /// `ErrorKind::Unsupported` before reaching zookeeper, instead of
+    /// failing with a permission error from the server.
+    pub fn read_only(&mut self) -> &mut Self {
+        self.read_only = true;
+        self
+    }
+
+    /// Store metadata like `content_type` along with values.
+    ///
+    /// ZooKeeper nodes carry nothing but bytes, with this option values are
+    /// prefixed with a small versioned header holding the `content_type`,
+    /// `content_disposition` and `cache_control` of the write, and stat
+    /// returns them. The header is stripped on read, and existing values
+    /// without it are read as is. Other clients reading the nodes directly
+    /// will see the header, so it's disabled by default and values are
+    /// stored unchanged.
+    pub fn store_metadata(&mut self) -> &mut Self {
+        self.store_metadata = true;
+        self
+    }
+
+    /// Delete the whole subtree when deleting a node with children.
+    ///
+    /// ZooKeeper refuses to delete a node that still has children. By
+    /// default such deletes fail with an error telling so, with this option
+    /// enabled the descendants are deleted bottom-up before the node itself.
+    /// The deletes are not atomic, a failure in between may leave the
+    /// subtree partially deleted.
+    pub fn recursive_delete(&mut self) -> &mut Self {
+        self.recursive_delete = true;
+        self
+    }
+
+    /// Require all endpoints to be reachable when connecting.
+    ///
+    /// By default, connecting succeeds as long as one of the comma separated
+    /// endpoints is reachable, following the ensemble semantics. With this
+    /// option enabled, every endpoint is probed with a TCP connection (3s
+    /// timeout) before connecting, and an error listing the failed endpoints
+    /// in the `failed_endpoints` context is returned if any of them can't be
+    /// reached. This is useful for environments where every configured
+    /// server must be healthy at startup.
+    pub fn require_all_endpoints(&mut self) -> &mut Self {
+        self.require_all_endpoints = true;
+        self
+    }
+
+    /// Set the proxy to tunnel connections to zookeeper through, like
+    /// `http://proxy:3128` for a http proxy supporting `CONNECT`, or
+    /// `socks5://proxy:1080` for a SOCKS5 proxy without authentication.
+    ///
+    /// The zookeeper client doesn't have a transport hook, so every server
+    /// of the ensemble is exposed on a local port forwarded through the
+    /// proxy for the client to connect to. Hostnames are resolved by the
+    /// proxy. `require_all_endpoints` is not checked through the proxy.
+    pub fn proxy(&mut self, proxy: &str) -> &mut Self {
+        if !proxy.is_empty() {
+            self.proxy = Some(proxy.to_string());
+        }
+        self
+    }
+
+    /// Set the label to prefix log messages with.
+    ///
+    /// All log messages of this service are emitted with target
+    /// `opendal::services::zookeeper`, the label is useful to tell apart the
+    /// messages from multiple backends, for example `[config] ...`.
+    pub fn label(&mut self, label: &str) -> &mut Self {
+        if !label.is_empty() {
+            self.label = Some(label.to_string());
+        }
+        self
+    }
+
+    /// Add a transformer to apply to values.
+    ///
+    /// Values are encoded by all transformers in the order they are added
+    /// before being written, and decoded in the reverse order after being
+    /// read. For example, add a compressor first to compress then encrypt.
+    pub fn transformer(&mut self, transformer: impl ZookeeperTransformer) -> &mut Self {
+        self.transformers.push(Arc::new(transformer));
+        self
+    }
+
+    /// Set the key to encrypt values client side with AES-256-GCM.
+    ///
+    /// The key must be 32 bytes. Encryption is applied after all
+    /// transformers added by [`ZookeeperBuilder::transformer`]. Encrypted
+    /// values carry a format header, values without it are rejected unless
+    /// [`ZookeeperBuilder::encryption_allow_plaintext`] is set. Values that
+    /// can't be decrypted return `ErrorKind::Unexpected` with a
+    /// `ZookeeperDecryptError` source.
+    #[cfg(feature = "services-zookeeper-encryption")]
+    pub fn encryption_key(&mut self, key: &[u8]) -> &mut Self {
+        if !key.is_empty() {
+            self.encryption_key = Some(key.to_vec());
+        }
+        self
+    }
+
+    /// Read values without the encryption header as plaintext.
+    ///
+    /// Only meant for migrating existing nodes to encryption gradually:
+    /// anyone who can write the nodes could inject plaintext values that
+    /// are accepted silently.
+    #[cfg(feature = "services-zookeeper-encryption")]
+    pub fn encryption_allow_plaintext(&mut self) -> &mut Self {
+        self.encryption_allow_plaintext = true;
+        self
+    }
 }
 
 impl Debug for ZookeeperBuilder {
@@ -98,48 +673,471 @@ impl Builder for ZookeeperBuilder {
 
     fn from_map(map: HashMap<String, String>) -> Self {
         let mut builder = ZookeeperBuilder::default();
+        let mut invalid = Vec::new();
 
         map.get("endpoint").map(|v| builder.endpoint(v));
+        map.get("root").map(|v| builder.root(v));
         map.get("username").map(|v| builder.username(v));
         map.get("password").map(|v| builder.password(v));
+        map.get("password_file").map(|v| builder.password_file(v));
+        map.get("auth_scheme").map(|v| builder.auth_scheme(v));
+        if let Some(v) = map.get("auths") {
+            for auth in v.split(',').filter(|v| !v.is_empty()) {
+                let (scheme, credential) = auth.split_once(':').unwrap_or((auth, ""));
+                builder.auth(scheme, credential);
+            }
+        }
+        if let Some(v) = map.get("acl") {
+            for acl in v.split(',').filter(|v| !v.is_empty()) {
+                // The id could contain `:` like `user:hash` of digest scheme.
+                let (scheme, rest) = acl.split_once(':').unwrap_or((acl, ""));
+                let (id, permissions) = rest.rsplit_once(':').unwrap_or((rest, ""));
+                builder.acl(scheme, id, permissions);
+            }
+        }
+        map.get("verify_auth")
+            .filter(|v| *v == "on" || *v == "true")
+            .map(|_| builder.verify_auth());
+        map.get("fallback_anonymous_reads")
+            .filter(|v| *v == "on" || *v == "true")
+            .map(|_| builder.fallback_anonymous_reads());
+        map.get("allow_anonymous")
+            .filter(|v| *v == "on" || *v == "true")
+            .map(|_| builder.allow_anonymous());
+        map.get("read_your_writes")
+            .filter(|v| *v == "on" || *v == "true")
+            .map(|_| builder.read_your_writes());
+        if let Some(v) = parse_config::<usize>(&map, "max_children_warn", &mut invalid) {
+            builder.max_children_warn(v);
+        }
+        if let Some(v) = parse_config::<usize>(&map, "max_children_error", &mut invalid) {
+            builder.max_children_error(v);
+        }
+        if let Some(v) = parse_config::<usize>(&map, "cache_capacity", &mut invalid) {
+            builder.cache_capacity(v);
+        }
+        if let Some(v) = parse_config::<usize>(&map, "cache_max_bytes", &mut invalid) {
+            builder.cache_max_bytes(v);
+        }
+        if let Some(v) = parse_config::<usize>(&map, "pool_size", &mut invalid) {
+            builder.pool_size(v);
+        }
+        if let Some(v) = parse_config::<usize>(&map, "max_node_size", &mut invalid) {
+            builder.max_node_size(v);
+        }
+        map.get("label").map(|v| builder.label(v));
+        map.get("require_all_endpoints")
+            .filter(|v| *v == "on" || *v == "true")
+            .map(|_| builder.require_all_endpoints());
+        map.get("strict_list")
+            .filter(|v| *v == "on" || *v == "true")
+            .map(|_| builder.strict_list());
+        map.get("read_only")
+            .filter(|v| *v == "on" || *v == "true")
+            .map(|_| builder.read_only());
+        map.get("store_metadata")
+            .filter(|v| *v == "on" || *v == "true")
+            .map(|_| builder.store_metadata());
+        map.get("recursive_delete")
+            .filter(|v| *v == "on" || *v == "true")
+            .map(|_| builder.recursive_delete());
+        map.get("proxy").map(|v| builder.proxy(v));
+        match map.get("durability").map(|v| v.as_str()) {
+            Some("quorum_ack") => {
+                builder.durability(ZookeeperDurability::QuorumAck);
+            }
+            Some("local_visible") => {
+                builder.durability(ZookeeperDurability::LocalVisible);
+            }
+            Some(_) => invalid.push(("durability".to_string(), "unknown value".to_string())),
+            None => {}
+        }
+        match map.get("create_mode").map(|v| v.as_str()) {
+            Some("persistent") => {
+                builder.create_mode(ZookeeperCreateMode::Persistent);
+            }
+            Some("ephemeral") => {
+                builder.create_mode(ZookeeperCreateMode::Ephemeral);
+            }
+            Some("persistent_sequential") => {
+                builder.create_mode(ZookeeperCreateMode::PersistentSequential);
+            }
+            Some("ephemeral_sequential") => {
+                builder.create_mode(ZookeeperCreateMode::EphemeralSequential);
+            }
+            Some(_) => invalid.push(("create_mode".to_string(), "unknown value".to_string())),
+            None => {}
+        }
+        #[cfg(feature = "services-zookeeper-encryption")]
+        if let Some(v) = map.get("encryption_key") {
+            match BASE64_STANDARD.decode(v) {
+                Ok(v) => {
+                    builder.encryption_key(&v);
+                }
+                Err(e) => invalid.push(("encryption_key".to_string(), e.to_string())),
+            }
+        }
+        #[cfg(feature = "services-zookeeper-encryption")]
+        map.get("encryption_allow_plaintext")
+            .filter(|v| *v == "on" || *v == "true")
+            .map(|_| builder.encryption_allow_plaintext());
+        if let Some(v) = parse_config::<u64>(&map, "cache_ttl", &mut invalid) {
+            builder.cache_ttl(Duration::from_secs(v));
+        }
+        if let Some(v) = parse_config::<u64>(&map, "session_timeout", &mut invalid) {
+            builder.session_timeout(Duration::from_secs(v));
+        }
+        if let Some(v) = parse_config::<u64>(&map, "connect_timeout", &mut invalid) {
+            builder.connect_timeout(Duration::from_secs(v));
+        }
+
+        // zookeeper-client 0.4 only speaks plaintext, fail instead of
+        // silently connecting without tls.
+        for key in ["enable_tls", "ca_cert", "client_cert", "client_key"] {
+            if map.contains_key(key) {
+                invalid.push((
+                    key.to_string(),
+                    "tls is not supported by zookeeper client".to_string(),
+                ));
+            }
+        }
 
+        builder.invalid_config = invalid;
         builder
     }
 
     fn build(&mut self) -> Result<Self::Accessor> {
-        let endpoint = match self.endpoint.clone() {
+        if let Some((key, reason)) = self.invalid_config.first() {
+            return Err(
+                Error::new(ErrorKind::ConfigInvalid, "config value is invalid")
+                    .with_operation("Builder::build")
+                    .with_context("service", Scheme::Zookeeper)
+                    .with_context("key", key)
+                    .with_context("reason", reason),
+            );
+        }
+        let endpoint = match self.endpoint.as_deref() {
             None => DEFAULT_ZOOKEEPER_ENDPOINT.to_string(),
-            Some(endpoint) => endpoint,
+            Some(endpoint) => {
+                let endpoint = normalize_endpoint(endpoint);
+                check_endpoint_format(&endpoint).map_err(|e| {
+                    e.with_operation("Builder::build")
+                        .with_context("service", Scheme::Zookeeper)
+                })?;
+                endpoint
+            }
+        };
+        let proxy = match &self.proxy {
+            Some(proxy) => Some(ZookeeperProxy::parse(proxy).map_err(|e| {
+                e.with_operation("Builder::build")
+                    .with_context("service", Scheme::Zookeeper)
+            })?),
+            None => None,
         };
-        let (auth, acl) = match (self.username.clone(), self.password.clone()) {
-            (Some(username), Some(password)) => {
-                let auth = format!("{username}:{password}").as_bytes().to_vec();
-                (auth, zk::Acl::creator_all())
+        let password = match (&self.password, &self.password_file) {
+            (Some(_), Some(_)) => {
+                return Err(Error::new(
+                    ErrorKind::ConfigInvalid,
+                    "password and password_file are both set",
+                )
+                .with_operation("Builder::build")
+                .with_context("service", Scheme::Zookeeper))
             }
-            _ => {
-                warn!("username and password isn't set, default use `anyone` acl");
-                (Vec::<u8>::new(), zk::Acl::anyone_all())
+            (Some(password), None) => Some(password.clone()),
+            (None, Some(path)) => {
+                let password = std::fs::read_to_string(path).map_err(|e| {
+                    Error::new(ErrorKind::ConfigInvalid, "failed to read password_file")
+                        .with_operation("Builder::build")
+                        .with_context("service", Scheme::Zookeeper)
+                        .with_context("password_file", path)
+                        .set_source(e)
+                })?;
+                Some(password.trim_end_matches(['\r', '\n']).to_string())
+            }
+            (None, None) => None,
+        };
+        if let Some(scheme) = self.auth_scheme.as_deref() {
+            if scheme != ZOOKEEPER_AUTH_SCHEME {
+                return Err(Error::new(
+                    ErrorKind::ConfigInvalid,
+                    "only digest auth is supported by zookeeper client",
+                )
+                .with_operation("Builder::build")
+                .with_context("service", Scheme::Zookeeper)
+                .with_context("auth_scheme", scheme));
+            }
+            if self.username.is_none() || password.is_none() {
+                return Err(Error::new(
+                    ErrorKind::ConfigInvalid,
+                    "auth_scheme is set but username or password is not set",
+                )
+                .with_operation("Builder::build")
+                .with_context("service", Scheme::Zookeeper)
+                .with_context("auth_scheme", scheme));
+            }
+        }
+        let mut auths = Vec::with_capacity(self.auths.len() + 1);
+        if let (Some(username), Some(password)) = (self.username.clone(), password) {
+            let auth = format!("{username}:{password}").as_bytes().to_vec();
+            auths.push((ZOOKEEPER_AUTH_SCHEME.to_string(), auth));
+        }
+        for (idx, (scheme, credential)) in self.auths.iter().enumerate() {
+            if scheme.is_empty() || credential.is_empty() {
+                return Err(Error::new(
+                    ErrorKind::ConfigInvalid,
+                    "auth scheme and credential must not be empty",
+                )
+                .with_operation("Builder::build")
+                .with_context("service", Scheme::Zookeeper)
+                .with_context("index", idx.to_string())
+                .with_context("scheme", scheme));
+            }
+            auths.push((scheme.clone(), credential.as_bytes().to_vec()));
+        }
+        let log_prefix = match &self.label {
+            Some(label) => format!("[{label}] "),
+            None => String::new(),
+        };
+        let acl = if !self.acls.is_empty() {
+            let mut acl = Vec::with_capacity(self.acls.len());
+            for (idx, (scheme, id, permissions)) in self.acls.iter().enumerate() {
+                let permission = parse_permission(permissions).ok_or_else(|| {
+                    Error::new(ErrorKind::ConfigInvalid, "acl permission is invalid")
+                        .with_operation("Builder::build")
+                        .with_context("service", Scheme::Zookeeper)
+                        .with_context("index", idx.to_string())
+                        .with_context("permissions", permissions)
+                })?;
+                acl.push(zk::Acl::new(permission, zk::AuthId::new(scheme, id)));
             }
+            acl
+        } else if auths.is_empty() {
+            if self.allow_anonymous {
+                debug!(
+                    target: LOGGING_TARGET,
+                    "{log_prefix}username and password isn't set, use `anyone` acl as allowed"
+                );
+            } else {
+                warn!(
+                    target: LOGGING_TARGET,
+                    "{log_prefix}username and password isn't set, default use `anyone` acl"
+                );
+            }
+            zk::Acl::anyone_all().to_vec()
+        } else {
+            zk::Acl::creator_all().to_vec()
         };
+        if let Some(timeout) = self.session_timeout {
+            if timeout < DEFAULT_ZOOKEEPER_TICK_TIME * 2
+                || timeout > DEFAULT_ZOOKEEPER_TICK_TIME * 20
+            {
+                return Err(Error::new(
+                    ErrorKind::ConfigInvalid,
+                    "session_timeout must be between 2 and 20 times of zookeeper tickTime",
+                )
+                .with_operation("Builder::build")
+                .with_context("service", Scheme::Zookeeper)
+                .with_context("session_timeout", format!("{timeout:?}")));
+            }
+        }
+        if self.verify_auth && auths.is_empty() {
+            return Err(Error::new(
+                ErrorKind::ConfigInvalid,
+                "verify_auth is set but no auth is configured",
+            )
+            .with_operation("Builder::build")
+            .with_context("service", Scheme::Zookeeper));
+        }
+
+        #[allow(unused_mut)]
+        let mut transformers = self.transformers.clone();
+        #[cfg(feature = "services-zookeeper-encryption")]
+        if let Some(key) = &self.encryption_key {
+            let mut transformer = AesGcmTransformer::new(key).map_err(|e| {
+                e.with_operation("Builder::build")
+                    .with_context("service", Scheme::Zookeeper)
+            })?;
+            if self.encryption_allow_plaintext {
+                transformer = transformer.allow_plaintext();
+            }
+            transformers.push(Arc::new(transformer));
+        }
 
         Ok(ZookeeperBackend::new(ZkAdapter {
             endpoint,
-            auth,
+            auths,
+            root: normalize_zk_path(self.root.as_deref().unwrap_or_default()).map_err(|e| {
+                e.with_operation("Builder::build")
+                    .with_context("service", Scheme::Zookeeper)
+            })?,
+            verify_auth: self.verify_auth,
+            fallback_anonymous_reads: self.fallback_anonymous_reads,
+            max_children_warn: self.max_children_warn,
+            max_children_error: self.max_children_error,
+            cache: ReadCache::new(self.cache_capacity, self.cache_max_bytes, self.cache_ttl)
+                .map(Arc::new),
+            recent_writes: self
+                .read_your_writes
+                .then(|| Arc::new(Mutex::new(HashMap::new()))),
             acl,
-            client: OnceCell::new(),
-        }))
+            transformers,
+            durability: self.durability,
+            create_mode: self.create_mode,
+            max_node_size: self.max_node_size.unwrap_or(DEFAULT_MAX_NODE_SIZE),
+            log_prefix,
+            label: self.label.clone(),
+            strict_list: self.strict_list,
+            read_only: self.read_only,
+            store_metadata: self.store_metadata,
+            recursive_delete: self.recursive_delete,
+            session_timeout: self.session_timeout,
+            connect_timeout: self.connect_timeout.unwrap_or(DEFAULT_CONNECT_TIMEOUT),
+            require_all_endpoints: self.require_all_endpoints,
+            stats: Arc::new(Stats::default()),
+            clients: Arc::new(
+                (0..self.pool_size.unwrap_or(1))
+                    .map(|_| ClientSlot::default())
+                    .collect(),
+            ),
+            next_client: Arc::new(AtomicUsize::new(0)),
+            runtime: Arc::default(),
+            proxy,
+            known_nodes: Arc::default(),
+            tunnel: Arc::default(),
+        })
+        .with_root(self.root.as_deref().unwrap_or_default()))
+    }
+}
+
+/// The acknowledgment level of writes to zookeeper.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ZookeeperDurability {
+    /// Writes return once they are committed by a quorum of the ensemble.
+    ///
+    /// This is how zookeeper always acks writes, a returned write will
+    /// survive the failure of any minority of servers. It's only guaranteed
+    /// to be visible to the connection issued it though, other connections
+    /// of the pool may be served by a lagging follower for a short while.
+    #[default]
+    QuorumAck,
+    /// Writes return once they are committed by a quorum, and visible to
+    /// all established connections of this backend.
+    ///
+    /// A `sync` is issued on every connection after the write, so that the
+    /// following reads through this backend will see it no matter which
+    /// connection they are dispatched to. This adds one round trip per
+    /// connection to every write.
+    LocalVisible,
+}
+
+/// The mode to create missing nodes with on write.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ZookeeperCreateMode {
+    /// The node lives until it's deleted.
+    #[default]
+    Persistent,
+    /// The node is deleted by the server once the session creating it ends.
+    ///
+    /// Sessions end on expiry, or when the backend is dropped, so the node
+    /// may disappear after a reconnect.
+    Ephemeral,
+    /// Like `Persistent`, but the server appends a monotonically increasing
+    /// 10 digits counter to the name of the node.
+    ///
+    /// The created node doesn't have the written path, so every write
+    /// creates a new node.
+    PersistentSequential,
+    /// Like `Ephemeral`, but the server appends a monotonically increasing
+    /// 10 digits counter to the name of the node.
+    EphemeralSequential,
+}
+
+impl From<ZookeeperCreateMode> for zk::CreateMode {
+    fn from(mode: ZookeeperCreateMode) -> Self {
+        match mode {
+            ZookeeperCreateMode::Persistent => zk::CreateMode::Persistent,
+            ZookeeperCreateMode::Ephemeral => zk::CreateMode::Ephemeral,
+            ZookeeperCreateMode::PersistentSequential => zk::CreateMode::PersistentSequential,
+            ZookeeperCreateMode::EphemeralSequential => zk::CreateMode::EphemeralSequential,
+        }
+    }
+}
+
+/// The session of a connection to zookeeper, returned by
+/// [`ZkAdapter::session`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ZookeeperSession {
+    /// The id of the session assigned by the server.
+    pub session_id: i64,
+    /// The session timeout negotiated with the server.
+    pub session_timeout: Duration,
+    /// The state of the session, like `SyncConnected`.
+    pub state: String,
+    /// The connect string the session has been established with.
+    ///
+    /// The zookeeper client doesn't tell which server of the ensemble is
+    /// serving the session, servers can be told apart by `session_id`
+    /// in the server logs instead.
+    pub endpoint: String,
+}
+
+impl ZookeeperSession {
+    fn new(client: &zk::Client, endpoint: &str) -> Self {
+        Self {
+            session_id: client.session_id().0,
+            session_timeout: client.session_timeout(),
+            state: client.state().to_string(),
+            endpoint: endpoint.to_string(),
+        }
     }
 }
 
 /// Backend for Zookeeper service
 pub type ZookeeperBackend = kv::Backend<ZkAdapter>;
 
+/// Adapter of the zookeeper service, exported as `ZookeeperAdapter`.
+///
+/// Helpers beyond the kv operations are called on the adapter of a built
+/// backend, see [`ZkAdapter::get_prefix`] for an example.
 #[derive(Clone)]
 pub struct ZkAdapter {
     endpoint: String,
-    auth: Vec<u8>,
-    client: OnceCell<zk::Client>,
-    acl: &'static [zk::Acl],
+    auths: Vec<(String, Vec<u8>)>,
+    /// The absolute root, in which the auth probe is created.
+    root: String,
+    verify_auth: bool,
+    fallback_anonymous_reads: bool,
+    max_children_warn: Option<usize>,
+    max_children_error: Option<usize>,
+    cache: Option<Arc<ReadCache>>,
+    /// The paths written by this backend with the write time, only tracked
+    /// when `read_your_writes` is enabled.
+    recent_writes: Option<Arc<Mutex<HashMap<String, Instant>>>>,
+    /// Shared by all clones, so that they reuse the same sessions.
+    clients: Arc<Vec<ClientSlot>>,
+    next_client: Arc<AtomicUsize>,
+    acl: Vec<zk::Acl>,
+    transformers: Vec<Arc<dyn ZookeeperTransformer>>,
+    durability: ZookeeperDurability,
+    create_mode: ZookeeperCreateMode,
+    max_node_size: usize,
+    /// The `[label] ` prepended to log messages, empty if label is not set.
+    log_prefix: String,
+    label: Option<String>,
+    strict_list: bool,
+    read_only: bool,
+    store_metadata: bool,
+    recursive_delete: bool,
+    require_all_endpoints: bool,
+    session_timeout: Option<Duration>,
+    connect_timeout: Duration,
+    stats: Arc<Stats>,
+    /// Started on first blocking operation and shared by all clones.
+    runtime: Arc<once_cell::sync::OnceCell<BlockingRuntime>>,
+    proxy: Option<ZookeeperProxy>,
+    known_nodes: Arc<KnownNodes>,
+    /// Started on first connect and shared by all clones.
+    tunnel: Arc<tokio::sync::OnceCell<ProxyTunnel>>,
 }
 
 impl Debug for ZkAdapter {
@@ -147,156 +1145,3360 @@ impl Debug for ZkAdapter {
         let mut ds = f.debug_struct("Adapter");
         ds.field("endpoint", &self.endpoint);
         ds.field("acl", &self.acl);
+        ds.field("pool_size", &self.clients.len());
+        ds.field("label", &self.label);
         ds.finish()
     }
 }
 
 impl ZkAdapter {
-    async fn get_connection(&self) -> Result<zk::Client> {
-        if let Some(client) = self.client.get() {
-            return Ok(client.clone());
-        }
-        match zk::Client::connect(&self.endpoint.clone()).await {
-            Ok(client) => {
-                if !self.auth.is_empty() {
-                    client
-                        .auth(ZOOKEEPER_AUTH_SCHEME.to_string(), self.auth.clone())
-                        .await
-                        .map_err(parse_zookeeper_error)?;
-                }
-                self.client.set(client.clone()).ok();
-                Ok(client)
-            }
-            Err(e) => Err(Error::new(ErrorKind::Unexpected, "error from zookeeper").set_source(e)),
+    pub(super) async fn get_connection(&self) -> Result<zk::Client> {
+        let idx = self.next_client.fetch_add(1, Ordering::Relaxed) % self.clients.len();
+        self.clients[idx].get_or_try_init(|| self.establish()).await
+    }
+
+    /// Run the future on the runtime owned by the adapter, blocking the
+    /// current thread until it completes.
+    ///
+    /// Blocking inside a runtime would stall its worker or panic, so calls
+    /// from a runtime context are rejected instead.
+    fn block_on<T>(&self, op: &'static str, fut: impl Future<Output = Result<T>>) -> Result<T> {
+        if tokio::runtime::Handle::try_current().is_ok() {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                "blocking zookeeper operations can't be called in a tokio runtime",
+            )
+            .with_operation(op));
         }
+
+        let runtime = self.runtime.get_or_try_init(|| {
+            tokio::runtime::Builder::new_multi_thread()
+                .worker_threads(1)
+                .thread_name("opendal-zookeeper")
+                .enable_all()
+                .build()
+                .map(|runtime| BlockingRuntime(Some(runtime)))
+                .map_err(|e| {
+                    Error::new(ErrorKind::Unexpected, "failed to start blocking runtime")
+                        .with_operation(op)
+                        .set_source(e)
+                })
+        })?;
+        runtime
+            .0
+            .as_ref()
+            .expect("runtime must be valid")
+            .block_on(fut)
     }
 
-    async fn create_nested_node(&self, path: &str, value: &[u8]) -> Result<()> {
-        let mut path = path.to_string();
-        if !path.starts_with('/') {
-            path = build_rooted_abs_path("/", path.strip_suffix('/').unwrap_or(&path));
-        }
-        let mut rend = path.len();
-        loop {
-            let mut subpath = path.substring(0, rend);
-            if subpath.is_empty() {
-                subpath = "/";
-            }
-            match self
-                .get_connection()
-                .await?
-                .create(
-                    subpath,
-                    value,
-                    &zk::CreateOptions::new(zk::CreateMode::Persistent, self.acl),
-                )
-                .await
-            {
-                Ok(_) => break Ok(()),
-                Err(e) => match e {
-                    zk::Error::NoNode => {
-                        rend = path.substring(0, rend).rfind('/').unwrap();
-                    }
-                    _ => {
-                        break Err(
-                            Error::new(ErrorKind::Unexpected, "error from zookeeper").set_source(e)
-                        )
-                    }
-                },
+    /// Connect to zookeeper and authenticate the new session.
+    async fn establish(&self) -> Result<zk::Client> {
+        let client = self.connect().await?;
+        let client = match self.authenticate(&client).await {
+            Ok(()) => {
+                if self.verify_auth {
+                    self.check_auth(&client).await?;
+                }
+                client
             }
-        }?;
-        match path.substring(rend + 1, path.len()).find('/') {
-            Some(len) => {
-                rend = len + rend + 1;
-                loop {
-                    match self
-                        .get_connection()
-                        .await?
-                        .create(
-                            path.substring(0, rend),
-                            value,
-                            &zk::CreateOptions::new(zk::CreateMode::Persistent, self.acl),
-                        )
-                        .await
-                    {
-                        Ok(_) => {
-                            if rend == path.len() {
-                                return Ok(());
-                            } else {
-                                match path.substring(rend + 1, path.len()).find('/') {
-                                    None => rend = path.len(),
-                                    Some(len) => rend = len + rend + 1,
-                                }
-                            }
-                        }
-                        Err(e) => {
-                            return Err(Error::new(ErrorKind::Unexpected, "error from zookeeper")
-                                .set_source(e))
-                        }
-                    }
+            Err(e) if self.fallback_anonymous_reads => {
+                warn!(
+                    target: LOGGING_TARGET,
+                    "{}auth to zookeeper failed, fallback to anonymous session: {e}",
+                    self.log_prefix
+                );
+                // Server will close the session once auth failed, so we need a new one.
+                self.connect().await?
+            }
+            Err(e) => return Err(e),
+        };
+        debug!(
+            target: LOGGING_TARGET,
+            "{}connected to zookeeper {} with session {}, timeout {:?}",
+            self.log_prefix,
+            self.endpoint,
+            client.session_id(),
+            client.session_timeout()
+        );
+        Ok(client)
+    }
+
+    /// Run the operation observed as `op`, and run it once more if it
+    /// failed because the session has expired or the connection has been
+    /// lost.
+    ///
+    /// Cached clients whose session has terminated are dropped before
+    /// retrying, so that the retry runs on a new session. Clients that are
+    /// only disconnected are kept, they reconnect on their own and keep the
+    /// session together with its ephemeral nodes and watches. The retry is
+    /// attempted only once to avoid looping under a hard outage.
+    async fn with_reconnect<T, Fut>(
+        &self,
+        op: &'static str,
+        path: &str,
+        f: impl Fn() -> Fut,
+    ) -> Result<T>
+    where
+        Fut: Future<Output = Result<T>>,
+    {
+        match self.observe(op, path, f()).await {
+            Err(e) if is_session_lost(&e) => {
+                warn!(
+                    target: LOGGING_TARGET,
+                    "{}zookeeper session lost, reconnecting: {e}", self.log_prefix
+                );
+                for slot in self.clients.iter() {
+                    slot.drop_terminated().await;
                 }
+                self.observe(op, path, f()).await
             }
-            None => Ok(()),
+            res => res,
         }
     }
-}
 
-#[async_trait]
-impl kv::Adapter for ZkAdapter {
-    fn metadata(&self) -> kv::Metadata {
-        kv::Metadata::new(
-            Scheme::Zookeeper,
-            "ZooKeeper",
-            Capability {
-                read: true,
-                write: true,
-                delete: true,
-                ..Default::default()
-            },
-        )
+    /// Run the future of given operation within a trace span, and record
+    /// its result into stats.
+    async fn observe<T>(
+        &self,
+        op: &'static str,
+        path: &str,
+        fut: impl Future<Output = Result<T>>,
+    ) -> Result<T> {
+        let res = trace::in_span(op, path, fut).await;
+        self.stats.record(op, &res);
+        res
     }
 
-    async fn get(&self, path: &str) -> Result<Option<Vec<u8>>> {
-        let path = build_rooted_abs_path("/", path.strip_suffix('/').unwrap_or(path));
-        match self.get_connection().await?.get_data(&path).await {
-            Ok(data) => Ok(Some(data.0)),
-            Err(e) => match e {
-                zk::Error::NoNode => Ok(None),
-                _ => Err(Error::new(ErrorKind::Unexpected, "error from zookeeper").set_source(e)),
-            },
+    async fn connect(&self) -> Result<zk::Client> {
+        self.observe("connect", "/", async {
+            let endpoint = match &self.proxy {
+                Some(proxy) => {
+                    let tunnel = self
+                        .tunnel
+                        .get_or_try_init(|| {
+                            ProxyTunnel::start(proxy, &self.endpoint, &self.log_prefix)
+                        })
+                        .await?;
+                    // Drop the error of previous connects.
+                    tunnel.take_error();
+                    tunnel.endpoint()
+                }
+                None => {
+                    if self.require_all_endpoints {
+                        check_endpoints(&self.endpoint).await?;
+                    }
+                    &self.endpoint
+                }
+            };
+            let mut builder = zk::Client::builder();
+            if let Some(timeout) = self.session_timeout {
+                builder.with_session_timeout(timeout);
+            }
+            let res =
+                match tokio::time::timeout(self.connect_timeout, builder.connect(endpoint)).await {
+                    Ok(res) => res.map_err(parse_zookeeper_error),
+                    Err(_) => Err(Error::new(
+                        ErrorKind::Unexpected,
+                        "timed out connecting to zookeeper",
+                    )
+                    .with_context("endpoint", &self.endpoint)
+                    .with_context("connect_timeout", format!("{:?}", self.connect_timeout))
+                    .set_temporary()),
+                };
+            // Failures of the proxy tell more than the client giving up.
+            res.map_err(
+                |e| match self.tunnel.get().and_then(|tunnel| tunnel.take_error()) {
+                    Some(proxy_err) => proxy_err.with_context("endpoint", &self.endpoint),
+                    None => e,
+                },
+            )
+        })
+        .await
+    }
+
+    async fn authenticate(&self, client: &zk::Client) -> Result<()> {
+        for (scheme, auth) in &self.auths {
+            client
+                .auth(scheme.clone(), auth.clone())
+                .await
+                .map_err(|e| parse_zookeeper_error(e).with_context("scheme", scheme))?;
         }
+        Ok(())
     }
 
-    async fn set(&self, path: &str, value: &[u8]) -> Result<()> {
-        let path = build_rooted_abs_path("/", path.strip_suffix('/').unwrap_or(path));
-        match self
-            .get_connection()
-            .await?
-            .set_data(&path, value, None)
+    /// Check that the auth has taken effect by creating a temporary node
+    /// with `creator_all` acl and checking its owner.
+    async fn check_auth(&self, client: &zk::Client) -> Result<()> {
+        // The root may not exist yet on a fresh server, the probe can't be
+        // created by `create_ancestors` since we are connecting.
+        let options = zk::CreateOptions::new(zk::CreateMode::Persistent, &self.acl);
+        let mut node = String::new();
+        for segment in self.root.split('/').filter(|v| !v.is_empty()) {
+            node = format!("{node}/{segment}");
+            match client.create(&node, &[], &options).await {
+                Ok(_) | Err(zk::Error::NodeExists) => {}
+                Err(e) => return Err(parse_zookeeper_error(e)),
+            }
+        }
+
+        let prefix = join_zookeeper_path(&self.root, "opendal-verify-auth-");
+        let options =
+            zk::CreateOptions::new(zk::CreateMode::EphemeralSequential, zk::Acl::creator_all());
+        let (_, sequence) = match client.create(&prefix, &[], &options).await {
+            Ok(v) => v,
+            Err(zk::Error::InvalidAcl) => {
+                return Err(Error::new(
+                    ErrorKind::ConfigInvalid,
+                    "auth didn't take effect, server doesn't recognize any authenticated id",
+                ))
+            }
+            Err(e) => return Err(parse_zookeeper_error(e)),
+        };
+        let path = format!("{prefix}{sequence}");
+        let acls = client.get_acl(&path).await.map(|(acls, _)| acls);
+        client
+            .delete(&path, None)
             .await
-        {
-            Ok(_) => Ok(()),
-            Err(e) => match e {
-                zk::Error::NoNode => {
-                    return self.create_nested_node(&path, value).await;
+            .map_err(parse_zookeeper_error)?;
+
+        let acls = acls.map_err(parse_zookeeper_error)?;
+        let recognized = self.auths.iter().any(|(scheme, credential)| {
+            acls.iter().any(|acl| {
+                if acl.scheme() != scheme {
+                    return false;
                 }
-                _ => Err(Error::new(ErrorKind::Unexpected, "error from zookeeper").set_source(e)),
-            },
+                if scheme != ZOOKEEPER_AUTH_SCHEME {
+                    return true;
+                }
+                // Digest ids are `user:hash` of the `user:password` credential.
+                let credential = String::from_utf8_lossy(credential);
+                let user = credential.split(':').next().unwrap_or_default();
+                acl.id().starts_with(&format!("{user}:"))
+            })
+        });
+        if !recognized {
+            return Err(Error::new(
+                ErrorKind::ConfigInvalid,
+                "auth didn't take effect, acl is not owned by any configured identity",
+            )
+            .with_context("root", &self.root));
         }
+        Ok(())
     }
 
-    async fn delete(&self, path: &str) -> Result<()> {
-        let path = build_rooted_abs_path("/", path.strip_suffix('/').unwrap_or(path));
-        match self.get_connection().await?.delete(&path, None).await {
-            Ok(()) => Ok(()),
-            Err(e) => match e {
-                zk::Error::NoNode => Ok(()),
-                _ => Err(Error::new(ErrorKind::Unexpected, "error from zookeeper").set_source(e)),
-            },
+    /// Encode the value by all transformers in order, and check that it
+    /// fits in `max_node_size`.
+    fn encode_value(&self, value: &[u8]) -> Result<Vec<u8>> {
+        self.encode_value_with(value, &ValueHeader::default())
+    }
+
+    /// Encode the value with header if `store_metadata` is enabled, the
+    /// header is prepended before applying transformers.
+    fn encode_value_with(&self, value: &[u8], header: &ValueHeader) -> Result<Vec<u8>> {
+        let mut value = if self.store_metadata {
+            header.encode(value)?
+        } else {
+            value.to_vec()
+        };
+        for transformer in &self.transformers {
+            value = transformer.encode(&value)?;
+        }
+        if value.len() > self.max_node_size {
+            return Err(Error::new(
+                ErrorKind::Unexpected,
+                "value is too large to be stored in a zookeeper node",
+            )
+            .with_context("size", value.len().to_string())
+            .with_context("max_node_size", self.max_node_size.to_string()));
         }
+        Ok(value)
+    }
+
+    /// Decode the value by all transformers in reverse order.
+    pub(super) fn decode_value(&self, value: Vec<u8>) -> Result<Vec<u8>> {
+        self.decode_value_with(value).map(|(_, value)| value)
     }
-}
 
-fn parse_zookeeper_error(e: zk::Error) -> Error {
-    Error::new(ErrorKind::Unexpected, "error from zookeeper").set_source(e)
+    /// Decode the value and split the header from it if `store_metadata`
+    /// is enabled.
+    fn decode_value_with(&self, value: Vec<u8>) -> Result<(ValueHeader, Vec<u8>)> {
+        let mut value = value;
+        for transformer in self.transformers.iter().rev() {
+            value = transformer.decode(&value)?;
+        }
+        if !self.store_metadata {
+            return Ok((ValueHeader::default(), value));
+        }
+        let (header, payload) = ValueHeader::decode(&value)?;
+        if payload.len() == value.len() {
+            return Ok((header, value));
+        }
+        Ok((header, payload.to_vec()))
+    }
+
+    /// Handle listing the missing node at `path` according to `strict_list`.
+    fn missing_list(&self, path: &str) -> Result<()> {
+        if self.strict_list {
+            return Err(
+                Error::new(ErrorKind::NotFound, "zookeeper node to list doesn't exist")
+                    .with_context("path", path),
+            );
+        }
+        Ok(())
+    }
+
+    /// Reject mutations with `ErrorKind::Unsupported` if `read_only` is set.
+    fn ensure_writable(&self) -> Result<()> {
+        if self.read_only {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                "zookeeper backend is read only",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Wait for the write to `path` to reach the configured durability.
+    async fn wait_durable(&self, path: &str) -> Result<()> {
+        if self.durability == ZookeeperDurability::QuorumAck {
+            return Ok(());
+        }
+
+        let mut clients = Vec::with_capacity(self.clients.len());
+        for slot in self.clients.iter() {
+            clients.extend(slot.get().await);
+        }
+        let syncs = clients.iter().map(|client| client.sync(path));
+        futures::future::try_join_all(syncs)
+            .await
+            .map_err(parse_zookeeper_error)?;
+        Ok(())
+    }
+
+    /// Record that `path` has just been written if `read_your_writes` is
+    /// enabled, expired records are dropped along the way.
+    fn mark_recent_write(&self, path: &str) {
+        if let Some(recent_writes) = &self.recent_writes {
+            let mut recent_writes = recent_writes.lock();
+            recent_writes.retain(|_, written| written.elapsed() < RECENT_WRITE_WINDOW);
+            recent_writes.insert(path.to_string(), Instant::now());
+        }
+    }
+
+    /// Check whether `path` has been written by us within the window.
+    fn is_recent_write(&self, path: &str) -> bool {
+        self.recent_writes
+            .as_ref()
+            .and_then(|recent_writes| {
+                recent_writes
+                    .lock()
+                    .get(path)
+                    .map(|written| written.elapsed() < RECENT_WRITE_WINDOW)
+            })
+            .unwrap_or_default()
+    }
+
+    /// Check the number of children of `parent` before creating a child under
+    /// it, against `max_children_warn` and `max_children_error`.
+    async fn check_children(&self, client: &zk::Client, parent: &str) -> Result<()> {
+        if self.max_children_warn.is_none() && self.max_children_error.is_none() {
+            return Ok(());
+        }
+
+        let children = match client.check_stat(parent).await {
+            Ok(Some(stat)) => stat.num_children as usize + 1,
+            // The parent will be created along with the child.
+            Ok(None) => return Ok(()),
+            Err(e) => return Err(parse_zookeeper_error(e)),
+        };
+        if let Some(max) = self.max_children_error {
+            if children > max {
+                return Err(Error::new(
+                    ErrorKind::Unexpected,
+                    "too many children under parent node",
+                )
+                .with_context("parent", parent)
+                .with_context("children", children.to_string())
+                .with_context("max_children_error", max.to_string()));
+            }
+        }
+        if let Some(max) = self.max_children_warn {
+            if children > max {
+                warn!(
+                    target: LOGGING_TARGET,
+                    "{}zookeeper node {parent} will have {children} children, exceeding {max}",
+                    self.log_prefix
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the create mode of a node, ancestors are always persistent since
+    /// ephemeral nodes can't have children.
+    fn node_create_mode(&self, is_leaf: bool) -> zk::CreateMode {
+        if is_leaf {
+            self.create_mode.into()
+        } else {
+            zk::CreateMode::Persistent
+        }
+    }
+
+    /// Create the node at `path` with `value` in the configured
+    /// `create_mode`, together with all its missing ancestors.
+    async fn create_nested_node(&self, path: &str, value: &[u8]) -> Result<()> {
+        self.create_nodes(path, value, |is_leaf| self.node_create_mode(is_leaf))
+            .await
+    }
+
+    /// Create the node at `path` and all its missing ancestors as empty
+    /// persistent nodes.
+    ///
+    /// Used for nodes that must last as parents or counters, which are
+    /// never created in the configured `create_mode`: ephemeral nodes can't
+    /// have children and sequential ones would get a suffix.
+    async fn create_ancestors(&self, path: &str) -> Result<()> {
+        self.create_nodes(path, &[], |_| zk::CreateMode::Persistent)
+            .await
+    }
+
+    async fn create_nodes(
+        &self,
+        path: &str,
+        value: &[u8],
+        mode: impl Fn(bool) -> zk::CreateMode,
+    ) -> Result<()> {
+        let client = self.get_connection().await?;
+        let mode = &mode;
+        create_with_ancestors(path, value, &self.known_nodes, |node, data, is_leaf| {
+            let client = &client;
+            async move {
+                let options = zk::CreateOptions::new(mode(is_leaf), &self.acl);
+                client.create(&node, data, &options).await.map(|_| ())
+            }
+        })
+        .await
+        .map_err(parse_zookeeper_error)
+    }
+
+    /// Get the decoded value at absolute `path`, served from the read cache
+    /// if possible.
+    async fn get_value(&self, path: &str) -> Result<Option<Vec<u8>>> {
+        if let Some(value) = self.cache.as_ref().and_then(|cache| cache.get(path)) {
+            return Ok(Some(value));
+        }
+        let client = self.get_connection().await?;
+        let mut res = client.get_data(path).await;
+        if matches!(res, Err(zk::Error::NoNode)) && self.is_recent_write(path) {
+            client.sync(path).await.map_err(parse_zookeeper_error)?;
+            res = client.get_data(path).await;
+        }
+        match res {
+            Ok((data, _)) => {
+                let data = self.decode_value(data)?;
+                if let Some(cache) = &self.cache {
+                    cache.insert(path, &data);
+                }
+                Ok(Some(data))
+            }
+            Err(e) => match e {
+                zk::Error::NoNode => Ok(None),
+                _ => Err(parse_zookeeper_error(e)),
+            },
+        }
+    }
+
+    /// Get at most `max_bytes` of the value stored at `path`.
+    ///
+    /// Returns the (possibly truncated) value together with a flag telling
+    /// whether it has been truncated, or `None` if the node doesn't exist.
+    ///
+    /// ZooKeeper always returns the whole node, so the full payload is still
+    /// transferred over the network. The truncation is purely applied on the
+    /// returned buffer.
+    ///
+    /// ```no_run
+    /// use opendal::services::Zookeeper;
+    /// use opendal::services::ZookeeperAdapter;
+    /// use opendal::Builder;
+    ///
+    /// async fn head(adapter: &ZookeeperAdapter) -> opendal::Result<()> {
+    ///     if let Some((data, truncated)) = adapter.get_prefix("large", 1024).await? {
+    ///         println!("read {} bytes, truncated: {truncated}", data.len());
+    ///     }
+    ///     Ok(())
+    /// }
+    ///
+    /// # async fn example() -> opendal::Result<()> {
+    /// let backend = Zookeeper::default().endpoint("127.0.0.1:2181").build()?;
+    /// head(backend.adapter()).await
+    /// # }
+    /// ```
+    pub async fn get_prefix(
+        &self,
+        path: &str,
+        max_bytes: usize,
+    ) -> Result<Option<(Vec<u8>, bool)>> {
+        let path = normalize_zk_path(path)?;
+        let path = &path;
+        let mut data = match self
+            .with_reconnect("get_prefix", path, || self.get_value(path))
+            .await?
+        {
+            Some(data) => data,
+            None => return Ok(None),
+        };
+        let truncated = data.len() > max_bytes;
+        data.truncate(max_bytes);
+        Ok(Some((data, truncated)))
+    }
+
+    /// Atomically claim a unique id from the counter node at `counter_path`.
+    ///
+    /// A persistent sequential child is created under the counter node and the
+    /// sequence number allocated by ZooKeeper is returned. The counter node
+    /// will be created if it doesn't exist.
+    ///
+    /// Only the number is needed, so the child is always deleted right after
+    /// creation. To keep the node, for example to store a value along with
+    /// the id, call [`ZkAdapter::create_sequential`] with `counter_path/`
+    /// instead, which draws from the same sequence.
+    ///
+    /// ZooKeeper derives the sequence from the parent's cversion which never
+    /// goes backwards, so ids are monotonically increasing across all clients.
+    /// They are not contiguous though: failed creations or any other child
+    /// created under the counter node will leave gaps.
+    pub async fn next_id(&self, counter_path: &str) -> Result<i64> {
+        self.ensure_writable()?;
+        let path = normalize_zk_path(counter_path)?;
+        let prefix = if path == "/" {
+            path.clone()
+        } else {
+            format!("{path}/")
+        };
+        let options = &zk::CreateOptions::new(zk::CreateMode::PersistentSequential, &self.acl);
+        let (path, prefix) = (&path, &prefix);
+
+        // Retrying on a lost session may claim another id, which only
+        // leaves a gap.
+        self.with_reconnect("next_id", path, || async move {
+            let client = self.get_connection().await?;
+            let sequence = match client.create(prefix, &[], options).await {
+                Ok((_, sequence)) => sequence,
+                Err(zk::Error::NoNode) => {
+                    self.create_ancestors(path).await?;
+                    let (_, sequence) = client
+                        .create(prefix, &[], options)
+                        .await
+                        .map_err(parse_zookeeper_error)?;
+                    sequence
+                }
+                Err(e) => return Err(parse_zookeeper_error(e)),
+            };
+
+            match client.delete(&format!("{prefix}{sequence}"), None).await {
+                Ok(()) | Err(zk::Error::NoNode) => Ok(sequence.0 as i64),
+                Err(e) => Err(parse_zookeeper_error(e)),
+            }
+        })
+        .await
+    }
+
+    /// Create a sequential node named `path` followed by the sequence number
+    /// allocated by ZooKeeper, and return the path of the created node.
+    ///
+    /// A sequential create is not idempotent: if the connection is lost
+    /// before the response arrives, there is no way to tell whether the node
+    /// has been created, and blindly retrying may leave a duplicate behind.
+    /// When a `token` is given, it's embedded in the node name as
+    /// `{path}{token}-{sequence}`. Before creating, the parent is listed for
+    /// an existing node carrying the same token and that node is reused
+    /// instead. The create is retried once on `ConnectionLoss` in this way,
+    /// and callers can safely retry with the same token as well.
+    ///
+    /// The token must be unique per logical create, and must not contain `/`.
+    pub async fn create_sequential(
+        &self,
+        path: &str,
+        value: &[u8],
+        ephemeral: bool,
+        token: Option<&str>,
+    ) -> Result<String> {
+        self.ensure_writable()?;
+        if let Some(token) = token {
+            if token.is_empty() || token.contains('/') {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    "idempotency token must be non-empty and must not contain '/'",
+                )
+                .with_operation("ZkAdapter::create_sequential")
+                .with_context("token", token));
+            }
+        }
+
+        // A trailing `/` creates the node as a child of `path`, keep it.
+        let normalized = normalize_zk_path(path)?;
+        let path = if path.ends_with('/') && normalized != "/" {
+            format!("{normalized}/")
+        } else {
+            normalized
+        };
+        let (parent, name) = match path.rfind('/') {
+            Some(0) => ("/", &path[1..]),
+            Some(idx) => (&path[..idx], &path[idx + 1..]),
+            None => unreachable!("rooted path must contain '/'"),
+        };
+        let name = match token {
+            Some(token) => format!("{name}{token}-"),
+            None => name.to_string(),
+        };
+        let prefix = join_zookeeper_path(parent, &name);
+        let mode = if ephemeral {
+            zk::CreateMode::EphemeralSequential
+        } else {
+            zk::CreateMode::PersistentSequential
+        };
+        let options = &zk::CreateOptions::new(mode, &self.acl);
+        let value = &self.encode_value(value)?;
+        let (name, prefix) = (&name, &prefix);
+
+        let create = || async move {
+            let client = self.get_connection().await?;
+            let mut retried = false;
+            loop {
+                if token.is_some() {
+                    if let Some(existing) = self.find_sequential(&client, parent, name).await? {
+                        return Ok(join_zookeeper_path(parent, &existing)[1..].to_string());
+                    }
+                }
+
+                self.check_children(&client, parent).await?;
+                match client.create(prefix, value, options).await {
+                    Ok((_, sequence)) => return Ok(format!("{prefix}{sequence}")[1..].to_string()),
+                    Err(zk::Error::NoNode) if !retried => {
+                        self.create_ancestors(parent).await?;
+                        retried = true;
+                    }
+                    Err(zk::Error::ConnectionLoss) if token.is_some() && !retried => {
+                        retried = true;
+                    }
+                    Err(e) => return Err(parse_zookeeper_error(e)),
+                }
+            }
+        };
+        // Without a token, the create could have been applied before the
+        // session is lost, so it's never retried on a new session.
+        if token.is_some() {
+            self.with_reconnect("create_sequential", prefix, create)
+                .await
+        } else {
+            self.observe("create_sequential", prefix, create()).await
+        }
+    }
+
+    /// Find the child of `parent` created by [`ZkAdapter::create_sequential`]
+    /// with given name prefix, the smallest sequence wins if there are many.
+    async fn find_sequential(
+        &self,
+        client: &zk::Client,
+        parent: &str,
+        name: &str,
+    ) -> Result<Option<String>> {
+        let children = match client.list_children(parent).await {
+            Ok(children) => children,
+            Err(zk::Error::NoNode) => return Ok(None),
+            Err(e) => return Err(parse_zookeeper_error(e)),
+        };
+
+        Ok(children
+            .into_iter()
+            .filter(|child| {
+                child
+                    .strip_prefix(name)
+                    .map(|seq| seq.len() == 10 && seq.bytes().all(|b| b.is_ascii_digit()))
+                    .unwrap_or_default()
+            })
+            .min())
+    }
+
+    /// List the children of `prefix` together with their metadata.
+    ///
+    /// The children names are fetched by one `list_children` call, and then
+    /// every child is stated with at most 16 requests in flight. This costs an
+    /// extra round trip per child compared to listing names only, but they
+    /// are pipelined over the same connection instead of being issued one by
+    /// one from user code.
+    ///
+    /// Children with their own children are returned as dirs ending with `/`.
+    /// Children removed between listing and stating are skipped, and a
+    /// missing `prefix` results in an empty list unless `strict_list` is
+    /// enabled.
+    pub async fn scan_with_metadata(&self, prefix: &str) -> Result<Vec<(String, Metadata)>> {
+        let path = normalize_zk_path(prefix)?;
+        let path = &path;
+        self.with_reconnect("scan_with_metadata", path, || async move {
+            let client = self.get_connection().await?;
+            let children = match client.list_children(path).await {
+                Ok(children) => children,
+                Err(zk::Error::NoNode) => return self.missing_list(path).map(|_| Vec::new()),
+                Err(e) => return Err(parse_zookeeper_error(e)),
+            };
+
+            let stats: Vec<(String, Option<zk::Stat>)> = stream::iter(children)
+                .map(|name| {
+                    let child = join_zookeeper_path(path, &name);
+                    let fut = client.check_stat(&child);
+                    async move { fut.await.map(|stat| (child, stat)) }
+                })
+                .buffered(STAT_CONCURRENCY)
+                .try_collect()
+                .await
+                .map_err(parse_zookeeper_error)?;
+
+            let mut entries = Vec::with_capacity(stats.len());
+            for (child, stat) in stats {
+                let Some(stat) = stat else { continue };
+                let meta = parse_stat_metadata(&stat)?;
+                let key = match meta.mode() {
+                    EntryMode::DIR => format!("{}/", &child[1..]),
+                    _ => child[1..].to_string(),
+                };
+                entries.push((key, meta));
+            }
+            Ok(entries)
+        })
+        .await
+    }
+
+    /// Scan all nodes under `path` together with their stat.
+    ///
+    /// Every level is listed by `get_children` with at most 16 requests in
+    /// flight, which returns the stat of the listed node at the same time.
+    async fn scan_stats(
+        &self,
+        operation: &'static str,
+        path: &str,
+    ) -> Result<Vec<(String, zk::Stat)>> {
+        let path = normalize_zk_path(path)?;
+        let path = &path;
+        self.with_reconnect(operation, path, || async move {
+            let client = self.get_connection().await?;
+            let children = match client.list_children(path).await {
+                Ok(children) => children,
+                Err(zk::Error::NoNode) => return self.missing_list(path).map(|_| Vec::new()),
+                Err(e) => return Err(parse_zookeeper_error(e)),
+            };
+
+            let mut entries = Vec::new();
+            let mut level: Vec<String> = children
+                .iter()
+                .map(|name| join_zookeeper_path(path, name))
+                .filter(|child| child != ZOOKEEPER_RESERVED_PATH)
+                .collect();
+            while !level.is_empty() {
+                // The stat comes along with the children of the node.
+                type Listed = Option<(Vec<String>, zk::Stat)>;
+                let listed: Vec<(String, Listed)> = stream::iter(level)
+                    .map(|child| {
+                        let fut = client.get_children(&child);
+                        async move {
+                            match fut.await {
+                                Ok(listed) => Ok((child, Some(listed))),
+                                // The child has been removed after listing.
+                                Err(zk::Error::NoNode) => Ok((child, None)),
+                                Err(e) => Err(e),
+                            }
+                        }
+                    })
+                    .buffered(STAT_CONCURRENCY)
+                    .try_collect()
+                    .await
+                    .map_err(parse_zookeeper_error)?;
+
+                level = Vec::new();
+                for (child, listed) in listed {
+                    let Some((children, stat)) = listed else {
+                        continue;
+                    };
+                    entries.push((scan_key(&child, !children.is_empty()), stat));
+                    level.extend(
+                        children
+                            .iter()
+                            .map(|name| join_zookeeper_path(&child, name)),
+                    );
+                }
+            }
+            // Children sort before their parents, so that deleting in the
+            // listed order never hits a non-empty node.
+            entries.sort_by(|(a, _), (b, _)| b.cmp(a));
+            Ok(entries)
+        })
+        .await
+    }
+
+    /// Set the same `value` to all `paths` under `prefix` in one transaction.
+    ///
+    /// This is a helper to seed a subtree with placeholder values, missing
+    /// `prefix` and ancestors between `prefix` and `paths` will be created
+    /// with empty value. Existing nodes will be overwritten only if
+    /// `overwrite` is set, otherwise they are left untouched. `paths` are
+    /// relative to `prefix` and validated like any other key, malformed ones
+    /// fail with `ErrorKind::ConfigInvalid` before anything is written.
+    ///
+    /// All creates and updates are committed in a single multi transaction,
+    /// so either all or none of them take effect.
+    pub async fn set_subtree(
+        &self,
+        prefix: &str,
+        paths: &[&str],
+        value: &[u8],
+        overwrite: bool,
+    ) -> Result<SubtreeReport> {
+        self.ensure_writable()?;
+        let prefix = normalize_zk_path(prefix)?;
+
+        // Collect leaves and their ancestors below prefix.
+        let mut leaves = HashSet::new();
+        let mut nodes = BTreeSet::new();
+        for path in paths {
+            let joined = join_zookeeper_path(&prefix, path);
+            if path.ends_with('/') {
+                return Err(
+                    Error::new(ErrorKind::ConfigInvalid, "invalid zookeeper path")
+                        .with_context("path", joined)
+                        .with_context("reason", "trailing slash"),
+                );
+            }
+            let leaf = normalize_zk_path(&joined)?;
+            let mut node = leaf.as_str();
+            while node != prefix && node != "/" {
+                nodes.insert(node.to_string());
+                node = parent_path(node);
+            }
+            if leaf != prefix {
+                leaves.insert(leaf);
+            }
+        }
+
+        let value = &self.encode_value(value)?;
+        let (prefix, leaves, nodes) = (&prefix, &leaves, &nodes);
+        // A retried commit that has been applied before the session is lost
+        // finds the nodes existing, and reports them as updated or skipped.
+        self.with_reconnect("set_subtree", prefix, || async move {
+            let client = self.get_connection().await?;
+            if client
+                .check_stat(prefix)
+                .await
+                .map_err(parse_zookeeper_error)?
+                .is_none()
+            {
+                self.create_ancestors(prefix).await?;
+            }
+
+            let mut stats: Vec<(String, Option<zk::Stat>)> = stream::iter(nodes.clone())
+                .map(|node| {
+                    let fut = client.check_stat(&node);
+                    async move { fut.await.map(|stat| (node, stat)) }
+                })
+                .buffered(STAT_CONCURRENCY)
+                .try_collect()
+                .await
+                .map_err(parse_zookeeper_error)?;
+
+            // Parents must be created before their children.
+            stats.sort_by_key(|(node, _)| node.matches('/').count());
+
+            let mut report = SubtreeReport::default();
+            let mut written = Vec::new();
+            let mut writer = client.new_multi_writer();
+            let options = zk::CreateOptions::new(zk::CreateMode::Persistent, &self.acl);
+            for (node, stat) in stats {
+                let is_leaf = leaves.contains(&node);
+                match (stat, is_leaf) {
+                    (None, true) => {
+                        writer
+                            .add_create(&node, value, &options)
+                            .map_err(parse_zookeeper_error)?;
+                        report.created.push(node[1..].to_string());
+                    }
+                    (None, false) => writer
+                        .add_create(&node, &[], &options)
+                        .map_err(parse_zookeeper_error)?,
+                    (Some(_), true) if overwrite => {
+                        writer
+                            .add_set_data(&node, value, None)
+                            .map_err(parse_zookeeper_error)?;
+                        report.updated.push(node[1..].to_string());
+                    }
+                    (Some(_), true) => {
+                        report.skipped.push(node[1..].to_string());
+                        continue;
+                    }
+                    (Some(_), false) => continue,
+                }
+                written.push(node);
+            }
+            writer.commit().await.map_err(parse_multi_write_error)?;
+
+            for node in &written {
+                if let Some(cache) = &self.cache {
+                    cache.remove(node);
+                }
+                self.mark_recent_write(node);
+            }
+            self.wait_durable(prefix).await?;
+            Ok(report)
+        })
+        .await
+    }
+
+    /// Get the session of the connection next operation is dispatched to,
+    /// connecting first if it's not established yet.
+    pub async fn session(&self) -> Result<ZookeeperSession> {
+        let client = self.get_connection().await?;
+        Ok(ZookeeperSession::new(&client, &self.endpoint))
+    }
+
+    /// Get the snapshot of counters of `connect`, `get`, `set` and `delete`
+    /// operations issued by this backend.
+    pub fn stats(&self) -> ZookeeperStats {
+        self.stats.snapshot()
+    }
+
+    /// Render [`ZkAdapter::stats`] in prometheus text exposition format.
+    ///
+    /// This is a convenience for services that don't have a metrics
+    /// pipeline, the output can be served at a scrape endpoint directly.
+    /// All samples are labeled with `endpoint`, and `label` if set.
+    #[cfg(feature = "services-zookeeper-prometheus")]
+    pub fn metrics_text(&self) -> String {
+        let mut labels = vec![("endpoint", self.endpoint.as_str())];
+        if let Some(label) = &self.label {
+            labels.push(("label", label.as_str()));
+        }
+        self.stats().to_prometheus_text(&labels)
+    }
+
+    /// Apply all `ops` in a single multi transaction, so either all or none
+    /// of them take effect.
+    ///
+    /// Operations are ordered topologically instead of by their position in
+    /// `ops`: missing ancestors of set paths are created first with empty
+    /// value, then sets are applied parents before children, and deletes are
+    /// applied last children before parents. So a whole tree can be seeded
+    /// or removed in one batch. If a path appears more than once, the last
+    /// operation on it wins. Deleting a missing node is a no-op like
+    /// `delete`.
+    ///
+    /// If the transaction fails, the returned error carries the `index` and
+    /// `path` of the failed operation in the ordered transaction.
+    pub async fn batch(&self, ops: Vec<ZookeeperBatchOp>) -> Result<()> {
+        self.ensure_writable()?;
+        let mut last = HashMap::new();
+        for op in ops {
+            let (path, value) = match op {
+                ZookeeperBatchOp::Set { path, value } => (path, Some(self.encode_value(&value)?)),
+                ZookeeperBatchOp::Delete { path } => (path, None),
+            };
+            let path = normalize_zk_path(&path)?;
+            last.insert(path, value);
+        }
+        // Retrying is safe, a commit applied before the session is lost
+        // plans no-op sets and deletes on the retry.
+        let (last, versions) = (&last, &HashMap::new());
+        self.with_reconnect("batch", "/", || self.commit_batch(last, versions))
+            .await
+    }
+
+    /// Commit the already encoded `ops` planned by `plan_batch` in a single
+    /// multi transaction.
+    ///
+    /// `ops` maps absolute paths to the value to set, or `None` to delete.
+    /// Paths in `versions` are set or deleted only if their version is
+    /// unchanged, otherwise the transaction fails with
+    /// `ErrorKind::ConditionNotMatch`.
+    async fn commit_batch(
+        &self,
+        ops: &HashMap<String, Option<Vec<u8>>>,
+        versions: &HashMap<String, i32>,
+    ) -> Result<()> {
+        if ops.is_empty() {
+            return Ok(());
+        }
+
+        // Stat all touched nodes and ancestors of set paths to plan creates.
+        let mut nodes = BTreeSet::new();
+        for (path, value) in ops {
+            nodes.insert(path.clone());
+            if value.is_some() {
+                let mut node = parent_path(path);
+                while node != "/" {
+                    nodes.insert(node.to_string());
+                    node = parent_path(node);
+                }
+            }
+        }
+        let client = self.get_connection().await?;
+        let existing: HashSet<String> = stream::iter(nodes)
+            .map(|node| {
+                let fut = client.check_stat(&node);
+                async move { fut.await.map(|stat| (node, stat)) }
+            })
+            .buffered(STAT_CONCURRENCY)
+            .try_filter_map(|(node, stat)| async move { Ok(stat.map(|_| node)) })
+            .try_collect()
+            .await
+            .map_err(parse_zookeeper_error)?;
+
+        let plan = plan_batch(ops, &existing);
+        let mut writer = client.new_multi_writer();
+        let options = zk::CreateOptions::new(zk::CreateMode::Persistent, &self.acl);
+        for op in &plan {
+            match op {
+                PlannedOp::Create(path, value) => writer.add_create(path, value, &options),
+                PlannedOp::SetData(path, value) => {
+                    writer.add_set_data(path, value, versions.get(path).copied())
+                }
+                PlannedOp::Delete(path) => writer.add_delete(path, versions.get(path).copied()),
+            }
+            .map_err(parse_zookeeper_error)?;
+        }
+        let res = writer.commit().await.map_err(|e| {
+            let op = match &e {
+                zk::MultiWriteError::OperationFailed { index, .. } => plan.get(*index),
+                zk::MultiWriteError::RequestFailed { .. } => None,
+            };
+            let err = parse_multi_write_error(e);
+            match op {
+                Some(op) => err.with_context("path", op.path()),
+                None => err,
+            }
+        });
+
+        for path in ops.keys() {
+            if let Some(cache) = &self.cache {
+                cache.remove(path);
+            }
+        }
+        res?;
+        for (path, value) in ops {
+            match value {
+                Some(_) => self.mark_recent_write(path),
+                None => {
+                    self.known_nodes.remove_subtree(path);
+                    if let Some(recent_writes) = &self.recent_writes {
+                        recent_writes.lock().remove(path);
+                    }
+                }
+            }
+        }
+        self.wait_durable("/").await
+    }
+
+    /// Swap the values of `path_a` and `path_b` atomically.
+    ///
+    /// Both values are read with their versions, and then written to each
+    /// other in one multi transaction that expects the versions unchanged.
+    /// If either node is modified concurrently, the swap is retried from
+    /// reading up to 3 times, after which `ErrorKind::ConditionNotMatch` is
+    /// returned. Either node missing returns `ErrorKind::NotFound`. On any
+    /// error, neither value has been changed, except when the session is
+    /// lost while committing: the swap may have been applied then, and it's
+    /// not retried. Swapping a path with itself is a no-op.
+    pub async fn swap(&self, path_a: &str, path_b: &str) -> Result<()> {
+        self.ensure_writable()?;
+        let path_a = normalize_zk_path(path_a)?;
+        let path_b = normalize_zk_path(path_b)?;
+        if path_a == path_b {
+            return Ok(());
+        }
+
+        let (path_a, path_b) = (&path_a, &path_b);
+        self.with_reconnect("swap", path_a, || self.swap_once(path_a, path_b))
+            .await
+    }
+
+    /// Swap the values of absolute `path_a` and `path_b` on one session.
+    async fn swap_once(&self, path_a: &str, path_b: &str) -> Result<()> {
+        let client = self.get_connection().await?;
+        let read = |path: String| {
+            let fut = client.get_data(&path);
+            async move {
+                match fut.await {
+                    Ok(v) => Ok(v),
+                    Err(zk::Error::NoNode) => Err(Error::new(
+                        ErrorKind::NotFound,
+                        "zookeeper node to swap doesn't exist",
+                    )
+                    .with_context("path", path)),
+                    Err(e) => Err(parse_zookeeper_error(e)),
+                }
+            }
+        };
+
+        for _ in 0..=SWAP_MAX_RETRIES {
+            let ((data_a, stat_a), (data_b, stat_b)) =
+                futures::future::try_join(read(path_a.to_string()), read(path_b.to_string()))
+                    .await?;
+
+            let mut writer = client.new_multi_writer();
+            writer
+                .add_set_data(path_a, &data_b, Some(stat_a.version))
+                .map_err(parse_zookeeper_error)?;
+            writer
+                .add_set_data(path_b, &data_a, Some(stat_b.version))
+                .map_err(parse_zookeeper_error)?;
+            match writer.commit().await {
+                Ok(_) => {
+                    for path in [path_a, path_b] {
+                        if let Some(cache) = &self.cache {
+                            cache.remove(path);
+                        }
+                        self.mark_recent_write(path);
+                    }
+                    return self.wait_durable(path_b).await;
+                }
+                Err(zk::MultiWriteError::OperationFailed {
+                    source: zk::Error::BadVersion,
+                    ..
+                }) => continue,
+                // The swap may have been applied, retrying on a new session
+                // would swap the values back.
+                Err(zk::MultiWriteError::RequestFailed {
+                    source: e @ (zk::Error::ConnectionLoss | zk::Error::SessionExpired),
+                }) => {
+                    return Err(Error::new(
+                        ErrorKind::Unexpected,
+                        "zookeeper session lost while committing swap, it may have been applied",
+                    )
+                    .with_context("zookeeper_error", e.to_string()))
+                }
+                Err(e) => return Err(parse_multi_write_error(e)),
+            }
+        }
+
+        Err(Error::new(
+            ErrorKind::ConditionNotMatch,
+            "zookeeper nodes to swap are modified concurrently",
+        )
+        .with_context("path_a", path_a)
+        .with_context("path_b", path_b)
+        .with_context("retries", SWAP_MAX_RETRIES.to_string()))
+    }
+
+    /// Check that zookeeper is reachable with a stat of the root node.
+    ///
+    /// This is cheap enough to back liveness and readiness probes. A lost
+    /// session is re-established like other operations, so an error means
+    /// a new session can't be established either.
+    pub async fn health_check(&self) -> Result<()> {
+        self.with_reconnect("health_check", "/", || async {
+            let client = self.get_connection().await?;
+            client
+                .check_stat("/")
+                .await
+                .map_err(parse_zookeeper_error)?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Check whether the node at `path` exists, without transferring its
+    /// value.
+    ///
+    /// `Operator::is_exist` goes through stat which doesn't transfer values
+    /// either, unless transformers are set and values have to be decoded to
+    /// get the length.
+    pub async fn exists(&self, path: &str) -> Result<bool> {
+        let path = normalize_zk_path(path)?;
+        let path = &path;
+        self.with_reconnect("exists", path, || async move {
+            let client = self.get_connection().await?;
+            client
+                .check_stat(path)
+                .await
+                .map(|stat| stat.is_some())
+                .map_err(parse_zookeeper_error)
+        })
+        .await
+    }
+
+    /// Set the value of `path` only if the node is still at the `expected`
+    /// version, and return the new version.
+    ///
+    /// Returns `ConditionNotMatch` if the node has been modified since, and
+    /// `NotFound` if it doesn't exist. The write is not retried on session
+    /// loss, since a write applied before the connection dropped would fail
+    /// the retry with a mismatched version.
+    pub async fn set_if_version(&self, path: &str, value: &[u8], expected: i32) -> Result<i32> {
+        self.ensure_writable()?;
+        let path = normalize_zk_path(path)?;
+        let stat = self
+            .observe(
+                "set_if_version",
+                &path,
+                self.set_version(&path, value, expected),
+            )
+            .await?;
+        Ok(stat.version)
+    }
+
+    /// Set the value of `path` only if its etag from stat still matches
+    /// `etag`, and return the new etag.
+    ///
+    /// Unlike [`ZkAdapter::set_if_version`], a node deleted and created
+    /// again doesn't match the etag of the previous one. Malformed etags
+    /// never match.
+    pub async fn set_if_match(&self, path: &str, value: &[u8], etag: &str) -> Result<String> {
+        self.ensure_writable()?;
+        let path = normalize_zk_path(path)?;
+        let not_match = || {
+            Error::new(
+                ErrorKind::ConditionNotMatch,
+                "zookeeper node doesn't match the etag",
+            )
+            .with_context("path", &path)
+            .with_context("etag", etag)
+        };
+        let (czxid, version) = parse_etag(etag).ok_or_else(not_match)?;
+
+        let stat = self
+            .observe("set_if_match", &path, async {
+                let client = self.get_connection().await?;
+                let stat = client
+                    .check_stat(&path)
+                    .await
+                    .map_err(parse_zookeeper_error)?
+                    .ok_or_else(|| {
+                        Error::new(ErrorKind::NotFound, "zookeeper node doesn't exist")
+                            .with_context("path", &path)
+                    })?;
+                // The version check of the write covers the node being
+                // created again as long as its version moves on, only a
+                // recreation back to the same version in between is missed.
+                if stat.czxid != czxid {
+                    return Err(not_match());
+                }
+                self.set_version(&path, value, version).await
+            })
+            .await?;
+        Ok(format_etag(&stat))
+    }
+
+    /// Write `value` to the existing node at `path` with given version.
+    async fn set_version(&self, path: &str, value: &[u8], version: i32) -> Result<zk::Stat> {
+        let value = self.encode_value(value)?;
+        let client = self.get_connection().await?;
+        let res = client.set_data(path, &value, Some(version)).await;
+        if let Some(cache) = &self.cache {
+            cache.remove(path);
+        }
+        let stat = res.map_err(|e| {
+            parse_zookeeper_error(e)
+                .with_context("path", path)
+                .with_context("version", version.to_string())
+        })?;
+        self.mark_recent_write(path);
+        self.wait_durable(path).await?;
+        Ok(stat)
+    }
+
+    /// Watch the children of `prefix` together with their values.
+    ///
+    /// Returns the current map from child names to values, and a stream of
+    /// incremental changes on top of it. The map is maintained by a children
+    /// watch on `prefix` and a data watch on every child, so only direct
+    /// children are covered. A missing `prefix` starts with an empty map and
+    /// is watched for creation.
+    ///
+    /// Zookeeper watches are one-shot, changes happened between a watch
+    /// firing and being registered again are observed as one, so consumers
+    /// get the latest value but not necessarily every intermediate one. If
+    /// the session is lost, the whole map is fetched again and a
+    /// [`ZookeeperMapEvent::Reset`] is emitted. The stream ends after
+    /// returning an error.
+    pub async fn watch_map(
+        &self,
+        prefix: &str,
+    ) -> Result<(
+        HashMap<String, Vec<u8>>,
+        BoxStream<'static, Result<ZookeeperMapEvent>>,
+    )> {
+        let prefix = normalize_zk_path(prefix)?;
+        let watcher = MapWatcher::new(self.clone(), prefix).await?;
+        Ok((watcher.snapshot(), watcher.into_stream()))
+    }
+
+    /// Watch the node at `path` for changes.
+    ///
+    /// Returns a stream of changes after the current state of the node, the
+    /// node is covered by a data watch, and a children watch while it
+    /// exists. A missing node is watched for creation.
+    ///
+    /// Zookeeper watches are one-shot, they are registered again every time
+    /// they fire, so changes happened in between are observed as one. If the
+    /// session is lost, the node is read again on a new session and the
+    /// change since last read is emitted if any. The stream ends after
+    /// returning an error, and the watches are dropped with the stream.
+    pub async fn watch(
+        &self,
+        path: &str,
+    ) -> Result<BoxStream<'static, Result<ZookeeperWatchEvent>>> {
+        let path = normalize_zk_path(path)?;
+        Ok(KeyWatcher::new(self.clone(), path).await?.into_stream())
+    }
+
+    /// Get the given `range` of the value stored at `path`, or `None` if the
+    /// node doesn't exist.
+    ///
+    /// If the read cache is enabled and has the value, the range is copied
+    /// from the cached buffer without any round trip to zookeeper, so
+    /// repeated partial reads of a cached value are almost free. The entry is
+    /// still subject to `cache_ttl` and invalidated by writes through this
+    /// backend. Otherwise, zookeeper always returns the whole node, so the
+    /// full value is fetched (and cached if enabled) before being sliced.
+    ///
+    /// Range reads via `Operator` go through the same cache.
+    pub async fn get_range(&self, path: &str, range: BytesRange) -> Result<Option<Vec<u8>>> {
+        let abs_path = normalize_zk_path(path)?;
+        if let Some(value) = self
+            .cache
+            .as_ref()
+            .and_then(|cache| cache.get_with(&abs_path, |value| slice_range(value, range).to_vec()))
+        {
+            return Ok(Some(value));
+        }
+
+        Ok(kv::Adapter::get(self, path)
+            .await?
+            .map(|value| slice_range(&value, range).to_vec()))
+    }
+
+    /// Compare the live subtree under `prefix` with the `desired` snapshot.
+    ///
+    /// Keys of `desired` and the returned diff are relative to `prefix`, for
+    /// example `a/b` stands for `{prefix}/a/b`. The live tree is walked
+    /// depth first with at most 16 reads in flight, and every value is
+    /// dropped right after being compared, so only the pending dirs are held
+    /// in memory besides `desired` itself.
+    ///
+    /// ZooKeeper nodes carry both children and value. Nodes with children and
+    /// an empty value are ancestors created along the way, they are only
+    /// compared if present in `desired` and never reported as removed. Leaves
+    /// with an empty value are compared like any other value. A missing
+    /// `prefix` is treated as an empty tree.
+    pub async fn diff(&self, prefix: &str, desired: HashMap<String, Vec<u8>>) -> Result<TreeDiff> {
+        let prefix = normalize_zk_path(prefix)?;
+        let mut desired: HashMap<String, Vec<u8>> = desired
+            .into_iter()
+            .map(|(k, v)| (k.trim_matches('/').to_string(), v))
+            .collect();
+
+        let mut diff = TreeDiff::default();
+        self.walk_tree("diff", &prefix, |key, data, stat| {
+            match desired.remove(&key) {
+                Some(value) => {
+                    if self.decode_value(data)? != value {
+                        diff.changed.push(key);
+                    }
+                }
+                None if stat.num_children > 0 && data.is_empty() => {}
+                None => diff.removed.push(key),
+            }
+            Ok(())
+        })
+        .await?;
+        diff.added = desired.into_keys().collect();
+
+        diff.added.sort();
+        diff.removed.sort();
+        diff.changed.sort();
+        Ok(diff)
+    }
+
+    /// Walk the subtree under `prefix` and report the nodes whose value
+    /// can't be decoded.
+    ///
+    /// This is the only check: a node is reported if its value fails to
+    /// be decoded by the configured transformers or the `store_metadata`
+    /// header, for example nodes encrypted with another key or written by a
+    /// client using a different format. Nodes with empty value are skipped
+    /// since they are either ancestors or intentionally empty. The backend
+    /// doesn't store chunks, sidecars or markers, so there are no orphans
+    /// of them to detect.
+    ///
+    /// This is read only and can't repair anything, reported nodes are
+    /// never modified. The walk has the same cost as [`ZkAdapter::diff`],
+    /// every node is read once.
+    pub async fn fsck(&self, prefix: &str) -> Result<FsckReport> {
+        let prefix = normalize_zk_path(prefix)?;
+
+        let mut report = FsckReport::default();
+        self.walk_tree("fsck", &prefix, |key, data, _| {
+            report.checked += 1;
+            if !data.is_empty() && self.decode_value(data).is_err() {
+                report.undecodable.push(key);
+            }
+            Ok(())
+        })
+        .await?;
+
+        report.undecodable.sort();
+        Ok(report)
+    }
+
+    /// Delete the node at absolute `path` with all its descendants
+    /// bottom-up, the paths visited are appended to `deleted`.
+    ///
+    /// Nodes removed concurrently are skipped, and children created
+    /// concurrently are picked up by listing again on `NotEmpty`.
+    async fn delete_tree(
+        &self,
+        client: &zk::Client,
+        path: &str,
+        deleted: &mut Vec<String>,
+    ) -> Result<()> {
+        self.ensure_writable()?;
+        // Nodes in pre-order, so that deleting in reverse order removes
+        // children before their parent.
+        let mut nodes = Vec::new();
+        let mut dirs = vec![path.to_string()];
+        while let Some(dir) = dirs.pop() {
+            let children = match client.list_children(&dir).await {
+                Ok(children) => children,
+                Err(zk::Error::NoNode) => continue,
+                Err(e) => return Err(parse_zookeeper_error(e)),
+            };
+            dirs.extend(children.iter().map(|name| join_zookeeper_path(&dir, name)));
+            nodes.push(dir);
+        }
+
+        while let Some(node) = nodes.pop() {
+            match client.delete(&node, None).await {
+                Ok(()) | Err(zk::Error::NoNode) => deleted.push(node),
+                Err(zk::Error::NotEmpty) => {
+                    // Children have been created in between, list again.
+                    nodes.push(node.clone());
+                    let children = client
+                        .list_children(&node)
+                        .await
+                        .map_err(parse_zookeeper_error)?;
+                    nodes.extend(children.iter().map(|name| join_zookeeper_path(&node, name)));
+                }
+                Err(e) => return Err(parse_zookeeper_error(e).with_context("path", node)),
+            }
+        }
+        Ok(())
+    }
+
+    /// Walk the subtree under the absolute `prefix` depth first, and visit
+    /// every node with its key relative to `prefix`, raw value and stat.
+    ///
+    /// Values are read with at most 16 requests in flight. Nodes removed
+    /// during the walk are skipped, and a missing `prefix` visits nothing.
+    async fn walk_tree(
+        &self,
+        op: &'static str,
+        prefix: &str,
+        mut visit: impl FnMut(String, Vec<u8>, &zk::Stat) -> Result<()>,
+    ) -> Result<()> {
+        // Collect the whole walk first, so that a walk retried on a new
+        // session doesn't visit nodes twice.
+        let nodes = self
+            .with_reconnect(op, prefix, || self.collect_tree(prefix))
+            .await?;
+        for (key, data, stat) in nodes {
+            visit(key, data, &stat)?;
+        }
+        Ok(())
+    }
+
+    async fn collect_tree(&self, prefix: &str) -> Result<Vec<(String, Vec<u8>, zk::Stat)>> {
+        let client = self.get_connection().await?;
+
+        let mut visited = Vec::new();
+        let mut dirs = vec![prefix.to_string()];
+        while let Some(dir) = dirs.pop() {
+            let children = match client.list_children(&dir).await {
+                Ok(children) => children,
+                Err(zk::Error::NoNode) => continue,
+                Err(e) => return Err(parse_zookeeper_error(e)),
+            };
+
+            let mut nodes = stream::iter(children)
+                .map(|name| {
+                    let child = join_zookeeper_path(&dir, &name);
+                    let fut = client.get_data(&child);
+                    async move { (child, fut.await) }
+                })
+                .buffered(STAT_CONCURRENCY);
+            while let Some((child, res)) = nodes.next().await {
+                let (data, stat) = match res {
+                    Ok(v) => v,
+                    // The child has been removed after listing.
+                    Err(zk::Error::NoNode) => continue,
+                    Err(e) => return Err(parse_zookeeper_error(e)),
+                };
+                let key = child[prefix.len()..].trim_start_matches('/').to_string();
+                if stat.num_children > 0 {
+                    dirs.push(child);
+                }
+                visited.push((key, data, stat));
+            }
+        }
+        Ok(visited)
+    }
+}
+
+/// The operation to apply in [`ZkAdapter::batch`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ZookeeperBatchOp {
+    /// Set the value of path, creating it and its ancestors if missing.
+    Set {
+        /// The path to set.
+        path: String,
+        /// The value to set.
+        value: Vec<u8>,
+    },
+    /// Delete the path if it exists.
+    Delete {
+        /// The path to delete.
+        path: String,
+    },
+}
+
+/// The operation planned into the multi transaction of a batch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum PlannedOp {
+    Create(String, Vec<u8>),
+    SetData(String, Vec<u8>),
+    Delete(String),
+}
+
+impl PlannedOp {
+    fn path(&self) -> &str {
+        match self {
+            PlannedOp::Create(path, _) | PlannedOp::SetData(path, _) | PlannedOp::Delete(path) => {
+                path
+            }
+        }
+    }
+}
+
+/// Order the batch operations topologically.
+///
+/// `ops` maps absolute paths to the value to set, or `None` to delete.
+/// `existing` contains the nodes that exist before the batch.
+fn plan_batch(
+    ops: &HashMap<String, Option<Vec<u8>>>,
+    existing: &HashSet<String>,
+) -> Vec<PlannedOp> {
+    let depth = |path: &str| path.matches('/').count();
+
+    let mut writes = BTreeMap::new();
+    let mut deletes = Vec::new();
+    for (path, value) in ops {
+        match value {
+            Some(value) => {
+                let op = if existing.contains(path) {
+                    PlannedOp::SetData(path.clone(), value.clone())
+                } else {
+                    PlannedOp::Create(path.clone(), value.clone())
+                };
+                writes.insert(path.clone(), op);
+            }
+            None if existing.contains(path) => deletes.push(PlannedOp::Delete(path.clone())),
+            None => {}
+        }
+    }
+    for path in ops.iter().filter(|(_, v)| v.is_some()).map(|(k, _)| k) {
+        let mut node = parent_path(path);
+        while node != "/" {
+            if !existing.contains(node) && !writes.contains_key(node) {
+                writes.insert(
+                    node.to_string(),
+                    PlannedOp::Create(node.to_string(), Vec::new()),
+                );
+            }
+            node = parent_path(node);
+        }
+    }
+
+    // Parents before children for writes, children before parents for deletes.
+    let mut plan: Vec<PlannedOp> = writes.into_values().collect();
+    plan.sort_by_key(|op| depth(op.path()));
+    deletes.sort_by(|a, b| {
+        depth(b.path())
+            .cmp(&depth(a.path()))
+            .then_with(|| a.path().cmp(b.path()))
+    });
+    plan.extend(deletes);
+    plan
+}
+
+/// The result of [`ZkAdapter::set_subtree`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SubtreeReport {
+    /// Keys of the nodes that have been created.
+    pub created: Vec<String>,
+    /// Keys of the existing nodes that have been overwritten.
+    pub updated: Vec<String>,
+    /// Keys of the existing nodes left untouched since overwrite is disabled.
+    pub skipped: Vec<String>,
+}
+
+/// The result of [`ZkAdapter::diff`], keys are relative to the compared prefix.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TreeDiff {
+    /// Keys in the desired snapshot but missing in the live tree.
+    pub added: Vec<String>,
+    /// Keys in the live tree but missing in the desired snapshot.
+    pub removed: Vec<String>,
+    /// Keys present in both but with different values.
+    pub changed: Vec<String>,
+}
+
+/// The result of [`ZkAdapter::fsck`], keys are relative to the checked prefix.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FsckReport {
+    /// The number of nodes that have been checked.
+    pub checked: usize,
+    /// Keys of the nodes whose value can't be decoded by the transformers.
+    pub undecodable: Vec<String>,
+}
+
+#[async_trait]
+impl kv::Adapter for ZkAdapter {
+    fn metadata(&self) -> kv::Metadata {
+        let writable = !self.read_only;
+        let with_metadata = writable && self.store_metadata;
+        kv::Metadata::new(
+            Scheme::Zookeeper,
+            "ZooKeeper",
+            Capability {
+                read: true,
+                read_with_if_none_match: true,
+                write: writable,
+                write_with_content_type: with_metadata,
+                write_with_content_disposition: with_metadata,
+                write_with_cache_control: with_metadata,
+                delete: writable,
+                list: true,
+                rename: writable,
+                batch: writable,
+                batch_delete: writable,
+                blocking: true,
+                ..Default::default()
+            },
+        )
+    }
+
+    async fn get(&self, path: &str) -> Result<Option<Vec<u8>>> {
+        let path = normalize_zk_path(path)?;
+        let path = &path;
+        self.with_reconnect("get", path, || self.get_value(path))
+            .await
+    }
+
+    async fn stat(&self, path: &str) -> Result<Option<Metadata>> {
+        let path = normalize_zk_path(path)?;
+        let path = &path;
+        self.with_reconnect("stat", path, || async move {
+            let client = self.get_connection().await?;
+            // Values are stored encoded by transformers or with header, read
+            // and decode them to get the actual length and metadata.
+            let (length, header, stat) = if self.transformers.is_empty() && !self.store_metadata {
+                match client.check_stat(path).await {
+                    Ok(Some(stat)) => (stat.data_length as u64, ValueHeader::default(), stat),
+                    Ok(None) => return Ok(None),
+                    Err(e) => return Err(parse_zookeeper_error(e)),
+                }
+            } else {
+                match client.get_data(path).await {
+                    Ok((data, stat)) => {
+                        let (header, value) = self.decode_value_with(data)?;
+                        (value.len() as u64, header, stat)
+                    }
+                    Err(zk::Error::NoNode) => return Ok(None),
+                    Err(e) => return Err(parse_zookeeper_error(e)),
+                }
+            };
+
+            let mut meta = parse_stat_metadata(&stat)?;
+            meta.set_mode(EntryMode::FILE);
+            meta.set_content_length(length);
+            header.apply(&mut meta);
+            Ok(Some(meta))
+        })
+        .await
+    }
+
+    async fn set(&self, path: &str, value: &[u8]) -> Result<()> {
+        self.set_with(path, value, &OpWrite::default()).await
+    }
+
+    async fn set_with(&self, path: &str, value: &[u8], args: &OpWrite) -> Result<()> {
+        self.ensure_writable()?;
+        let path = normalize_zk_path(path)?;
+        let path = &path;
+        let header = &ValueHeader::from_op(args);
+        self.with_reconnect("set", path, || async move {
+            let value = &self.encode_value_with(value, header)?;
+            let client = self.get_connection().await?;
+            let res = match client.set_data(path, value, None).await {
+                Ok(_) => Ok(()),
+                Err(e) => match e {
+                    zk::Error::NoNode => {
+                        match self.check_children(&client, parent_path(path)).await {
+                            Ok(()) => self.create_nested_node(path, value).await,
+                            Err(e) => Err(e),
+                        }
+                    }
+                    _ => Err(parse_zookeeper_error(e)),
+                },
+            };
+            if let Some(cache) = &self.cache {
+                cache.remove(path);
+            }
+            if res.is_ok() {
+                self.mark_recent_write(path);
+                self.wait_durable(path).await?;
+            }
+            res
+        })
+        .await
+    }
+
+    async fn delete(&self, path: &str) -> Result<()> {
+        self.ensure_writable()?;
+        let path = normalize_zk_path(path)?;
+        let path = &path;
+        self.with_reconnect("delete", path, || async move {
+            let client = self.get_connection().await?;
+            let mut deleted = vec![path.clone()];
+            let res = match client.delete(path, None).await {
+                Ok(()) => Ok(()),
+                Err(e) => match e {
+                    zk::Error::NoNode => Ok(()),
+                    zk::Error::NotEmpty if self.recursive_delete => {
+                        self.delete_tree(&client, path, &mut deleted).await
+                    }
+                    zk::Error::NotEmpty => Err(Error::new(
+                        ErrorKind::Unexpected,
+                        "zookeeper node to delete still has children",
+                    )
+                    .with_context("path", path)
+                    .set_source(e)),
+                    _ => Err(parse_zookeeper_error(e)),
+                },
+            };
+            for path in &deleted {
+                self.known_nodes.remove_subtree(path);
+                if let Some(cache) = &self.cache {
+                    cache.remove(path);
+                }
+                if let Some(recent_writes) = &self.recent_writes {
+                    recent_writes.lock().remove(path);
+                }
+            }
+            if res.is_ok() {
+                self.wait_durable(path).await?;
+            }
+            res
+        })
+        .await
+    }
+
+    async fn rename(&self, from: &str, to: &str) -> Result<()> {
+        self.ensure_writable()?;
+        let from = normalize_zk_path(from)?;
+        let to = normalize_zk_path(to)?;
+        if from == to {
+            return Ok(());
+        }
+
+        let (from, to) = (&from, &to);
+        self.with_reconnect("rename", from, || async move {
+            // Move the raw value, so that the header of `store_metadata` is
+            // kept and transformed values are not decoded on the way.
+            let client = self.get_connection().await?;
+            let (value, stat) = match client.get_data(from).await {
+                Ok(v) => v,
+                Err(zk::Error::NoNode) => {
+                    return Err(Error::new(
+                        ErrorKind::NotFound,
+                        "zookeeper node to rename doesn't exist",
+                    )
+                    .with_context("path", from))
+                }
+                Err(e) => return Err(parse_zookeeper_error(e)),
+            };
+            // Write the destination and delete the source in one transaction,
+            // so the value is never lost nor duplicated on failure. The source
+            // is deleted at the version read, so a concurrent write to it
+            // fails the rename instead of being dropped.
+            let ops = HashMap::from([(to.clone(), Some(value)), (from.clone(), None)]);
+            let versions = HashMap::from([(from.clone(), stat.version)]);
+            self.commit_batch(&ops, &versions).await
+        })
+        .await
+    }
+
+    async fn copy(&self, from: &str, to: &str) -> Result<()> {
+        self.ensure_writable()?;
+        let from = normalize_zk_path(from)?;
+        let to = normalize_zk_path(to)?;
+        if from == to {
+            return Ok(());
+        }
+
+        let (from, to) = (&from, &to);
+        self.with_reconnect("copy", from, || async move {
+            // Copy the raw value like rename, to keep the header of
+            // `store_metadata`.
+            let client = self.get_connection().await?;
+            let value = match client.get_data(from).await {
+                Ok((value, _)) => value,
+                Err(zk::Error::NoNode) => {
+                    return Err(Error::new(
+                        ErrorKind::NotFound,
+                        "zookeeper node to copy doesn't exist",
+                    )
+                    .with_context("path", from))
+                }
+                Err(e) => return Err(parse_zookeeper_error(e)),
+            };
+            let ops = HashMap::from([(to.clone(), Some(value))]);
+            self.commit_batch(&ops, &HashMap::new()).await
+        })
+        .await
+    }
+
+    async fn batch_delete(&self, paths: &[String]) -> Result<()> {
+        self.ensure_writable()?;
+        let ops = &paths
+            .iter()
+            .map(|path| Ok((normalize_zk_path(path)?, None)))
+            .collect::<Result<HashMap<_, _>>>()?;
+        let versions = &HashMap::new();
+        self.with_reconnect("batch_delete", "/", || self.commit_batch(ops, versions))
+            .await
+    }
+
+    async fn scan(&self, path: &str) -> Result<Vec<String>> {
+        let entries = self.scan_stats("scan", path).await?;
+        Ok(entries.into_iter().map(|(key, _)| key).collect())
+    }
+
+    async fn scan_entries(&self, path: &str) -> Result<Vec<(String, Metadata)>> {
+        let entries = self.scan_stats("scan_entries", path).await?;
+        entries
+            .into_iter()
+            .map(|(key, stat)| {
+                if key.ends_with('/') {
+                    return Ok((key, Metadata::new(EntryMode::DIR)));
+                }
+                // Values encoded by transformers or with header don't have
+                // the actual length in stat, leave them to be stated.
+                if !self.transformers.is_empty() || self.store_metadata {
+                    return Ok((key, Metadata::new(EntryMode::FILE)));
+                }
+                let mut meta = parse_stat_metadata(&stat)?;
+                meta.set_mode(EntryMode::FILE);
+                Ok((key, meta))
+            })
+            .collect()
+    }
+
+    fn blocking_get(&self, path: &str) -> Result<Option<Vec<u8>>> {
+        self.block_on("kv::Adapter::blocking_get", self.get(path))
+    }
+
+    fn blocking_stat(&self, path: &str) -> Result<Option<Metadata>> {
+        self.block_on("kv::Adapter::blocking_stat", self.stat(path))
+    }
+
+    fn blocking_set(&self, path: &str, value: &[u8]) -> Result<()> {
+        self.block_on("kv::Adapter::blocking_set", self.set(path, value))
+    }
+
+    fn blocking_set_with(&self, path: &str, value: &[u8], args: &OpWrite) -> Result<()> {
+        self.block_on(
+            "kv::Adapter::blocking_set_with",
+            self.set_with(path, value, args),
+        )
+    }
+
+    fn blocking_delete(&self, path: &str) -> Result<()> {
+        self.block_on("kv::Adapter::blocking_delete", self.delete(path))
+    }
+
+    fn blocking_rename(&self, from: &str, to: &str) -> Result<()> {
+        self.block_on("kv::Adapter::blocking_rename", self.rename(from, to))
+    }
+
+    fn blocking_copy(&self, from: &str, to: &str) -> Result<()> {
+        self.block_on("kv::Adapter::blocking_copy", self.copy(from, to))
+    }
+
+    fn blocking_scan(&self, path: &str) -> Result<Vec<String>> {
+        self.block_on("kv::Adapter::blocking_scan", self.scan(path))
+    }
+
+    fn blocking_scan_entries(&self, path: &str) -> Result<Vec<(String, Metadata)>> {
+        self.block_on(
+            "kv::Adapter::blocking_scan_entries",
+            self.scan_entries(path),
+        )
+    }
+}
+
+/// The runtime to run blocking operations on.
+///
+/// It's shut down in background on drop, since dropping a runtime in an
+/// async context panics.
+struct BlockingRuntime(Option<tokio::runtime::Runtime>);
+
+impl Drop for BlockingRuntime {
+    fn drop(&mut self) {
+        if let Some(runtime) = self.0.take() {
+            runtime.shutdown_background();
+        }
+    }
+}
+
+/// The cached client of a connection in the pool.
+///
+/// Clients with a terminated session are never returned, so that a new
+/// session is established on next use. The lock is held while connecting,
+/// so concurrent first uses share one connection.
+#[derive(Default)]
+struct ClientSlot(tokio::sync::Mutex<Option<zk::Client>>);
+
+impl ClientSlot {
+    /// Get the cached client if its session is still alive.
+    async fn get(&self) -> Option<zk::Client> {
+        self.0
+            .lock()
+            .await
+            .clone()
+            .filter(|client| !client.state().is_terminated())
+    }
+
+    /// Get the cached client, or establish a new one by `f` if there is no
+    /// alive client.
+    async fn get_or_try_init<F, Fut>(&self, f: F) -> Result<zk::Client>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<zk::Client>>,
+    {
+        let mut client = self.0.lock().await;
+        if let Some(c) = client.as_ref() {
+            if !c.state().is_terminated() {
+                return Ok(c.clone());
+            }
+        }
+        let c = f().await?;
+        *client = Some(c.clone());
+        Ok(c)
+    }
+
+    /// Drop the cached client if its session is in a terminal state.
+    async fn drop_terminated(&self) {
+        let mut client = self.0.lock().await;
+        if let Some(c) = client.as_ref() {
+            if matches!(
+                c.state(),
+                zk::SessionState::Expired | zk::SessionState::AuthFailed | zk::SessionState::Closed
+            ) {
+                *client = None;
+            }
+        }
+    }
+}
+
+/// Check whether the error is caused by a lost zookeeper session.
+fn is_session_lost(e: &Error) -> bool {
+    matches!(
+        std::error::Error::source(e).and_then(|e| e.downcast_ref::<zk::Error>()),
+        Some(zk::Error::SessionExpired | zk::Error::ConnectionLoss)
+    )
+}
+
+/// Parse permissions like `rw` or `all` into zookeeper permission.
+fn parse_permission(permissions: &str) -> Option<zk::Permission> {
+    if permissions == "all" {
+        return Some(zk::Permission::ALL);
+    }
+    if permissions.is_empty() {
+        return None;
+    }
+    permissions.chars().try_fold(zk::Permission::NONE, |p, c| {
+        let v = match c {
+            'r' => zk::Permission::READ,
+            'w' => zk::Permission::WRITE,
+            'c' => zk::Permission::CREATE,
+            'd' => zk::Permission::DELETE,
+            'a' => zk::Permission::ADMIN,
+            _ => return None,
+        };
+        Some(p | v)
+    })
+}
+
+/// Build the key returned by scan from the absolute node path, nodes with
+/// children are returned as dirs ending with `/`.
+fn scan_key(path: &str, is_dir: bool) -> String {
+    let key = path.trim_start_matches('/');
+    if is_dir {
+        format!("{key}/")
+    } else {
+        key.to_string()
+    }
+}
+
+/// Validate the path against the rules of zookeeper, and convert it into an
+/// absolute path without trailing `/`.
+///
+/// Empty, `.` or `..` segments and characters not allowed by zookeeper are
+/// rejected with `ErrorKind::ConfigInvalid` before reaching the server.
+fn normalize_zk_path(path: &str) -> Result<String> {
+    let trimmed = path.strip_suffix('/').unwrap_or(path);
+    let trimmed = trimmed.strip_prefix('/').unwrap_or(trimmed);
+    if trimmed.is_empty() {
+        return Ok("/".to_string());
+    }
+
+    let invalid = |reason: &str| {
+        Error::new(ErrorKind::ConfigInvalid, "invalid zookeeper path")
+            .with_context("path", path)
+            .with_context("reason", reason)
+    };
+    for segment in trimmed.split('/') {
+        match segment {
+            "" => return Err(invalid("empty segment")),
+            "." | ".." => return Err(invalid("relative segment")),
+            _ => {}
+        }
+    }
+    if let Some(c) = trimmed.chars().find(|c| is_illegal_zk_char(*c)) {
+        return Err(invalid(&format!("illegal character {c:?}")));
+    }
+    Ok(format!("/{trimmed}"))
+}
+
+/// Returns whether the character is not allowed in zookeeper paths, as
+/// defined by `PathUtils.validatePath` of the server.
+fn is_illegal_zk_char(c: char) -> bool {
+    matches!(c,
+        '\u{0}'..='\u{1f}' | '\u{7f}'..='\u{9f}' | '\u{e000}'..='\u{f8ff}' | '\u{fff0}'..='\u{ffff}')
+}
+
+/// Get the parent of the absolute path, the parent of `/` is `/` itself.
+fn parent_path(path: &str) -> &str {
+    match path.rfind('/') {
+        Some(0) | None => "/",
+        Some(idx) => &path[..idx],
+    }
+}
+
+/// The nodes known to exist, so that creating nested nodes under them
+/// doesn't walk the ancestors from the root again.
+///
+/// Nodes deleted by other clients are not noticed, creating under a stale
+/// node fails with `NoNode` and falls back to the full walk.
+#[derive(Default)]
+struct KnownNodes(Mutex<HashSet<String>>);
+
+impl KnownNodes {
+    fn contains(&self, path: &str) -> bool {
+        self.0.lock().contains(path)
+    }
+
+    fn insert(&self, path: &str) {
+        let mut nodes = self.0.lock();
+        // Start over instead of tracking recency, the nodes are known again
+        // by the next walk.
+        if nodes.len() >= KNOWN_NODES_CAPACITY {
+            nodes.clear();
+        }
+        nodes.insert(path.to_string());
+    }
+
+    /// Forget the node and all its descendants.
+    fn remove_subtree(&self, path: &str) {
+        let prefix = format!("{path}/");
+        self.0
+            .lock()
+            .retain(|node| node != path && !node.starts_with(&prefix));
+    }
+}
+
+/// Create the node at `path` with `value` by `create`, which is called with
+/// the path of the node to create, its data and whether it's the leaf.
+///
+/// The leaf is created directly at first. If its parent is missing, the
+/// ancestors are created from top to bottom with empty data, starting below
+/// the deepest one in `known`, the ones already existed are skipped, and
+/// then the leaf is created again. Empty segments of `path` are ignored.
+async fn create_with_ancestors<'a, F, Fut>(
+    path: &str,
+    value: &'a [u8],
+    known: &KnownNodes,
+    create: F,
+) -> std::result::Result<(), zk::Error>
+where
+    F: Fn(String, &'a [u8], bool) -> Fut,
+    Fut: Future<Output = std::result::Result<(), zk::Error>>,
+{
+    let mut nodes = Vec::new();
+    let mut node = String::new();
+    for segment in path.split('/').filter(|s| !s.is_empty()) {
+        node.push('/');
+        node.push_str(segment);
+        nodes.push(node.clone());
+    }
+    let Some(leaf) = nodes.pop() else {
+        return create("/".to_string(), value, true).await;
+    };
+
+    match create(leaf.clone(), value, true).await {
+        Err(zk::Error::NoNode) => {}
+        Ok(()) => {
+            if let Some(parent) = nodes.last() {
+                known.insert(parent);
+            }
+            return Ok(());
+        }
+        res => return res,
+    }
+
+    let mut start = nodes
+        .iter()
+        .rposition(|node| known.contains(node))
+        .map_or(0, |idx| idx + 1);
+    'walk: loop {
+        for ancestor in &nodes[start..] {
+            match create(ancestor.clone(), &[], false).await {
+                Ok(()) | Err(zk::Error::NodeExists) => known.insert(ancestor),
+                // The known node has been deleted, walk from the root.
+                Err(zk::Error::NoNode) if start > 0 => {
+                    known.remove_subtree(&nodes[start - 1]);
+                    start = 0;
+                    continue 'walk;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        break;
+    }
+    create(leaf, value, true).await
+}
+
+/// Parse the value of `key` in the config map, the key and reason are pushed
+/// to `invalid` if it fails to parse.
+fn parse_config<T>(
+    map: &HashMap<String, String>,
+    key: &str,
+    invalid: &mut Vec<(String, String)>,
+) -> Option<T>
+where
+    T: std::str::FromStr,
+    T::Err: std::fmt::Display,
+{
+    match map.get(key)?.parse() {
+        Ok(v) => Some(v),
+        Err(e) => {
+            invalid.push((key.to_string(), e.to_string()));
+            None
+        }
+    }
+}
+
+/// Trim the servers of the comma separated connect string and drop empty
+/// ones, the chroot path is kept as is.
+fn normalize_endpoint(endpoint: &str) -> String {
+    let (hosts, chroot) = match endpoint.find('/') {
+        Some(idx) => endpoint.split_at(idx),
+        None => (endpoint, ""),
+    };
+    let hosts: Vec<&str> = hosts
+        .split(',')
+        .map(|v| v.trim())
+        .filter(|v| !v.is_empty())
+        .collect();
+    format!("{}{chroot}", hosts.join(","))
+}
+
+/// Check that every server of the connect string is in `host:port` form,
+/// ipv6 addresses should be enclosed in brackets like `[::1]:2181`.
+fn check_endpoint_format(endpoint: &str) -> Result<()> {
+    let hosts = match endpoint.find('/') {
+        Some(idx) => &endpoint[..idx],
+        None => endpoint,
+    };
+    if hosts.is_empty() {
+        return Err(Error::new(
+            ErrorKind::ConfigInvalid,
+            "endpoint doesn't contain any server",
+        )
+        .with_context("endpoint", endpoint));
+    }
+
+    for server in hosts.split(',') {
+        let invalid = |msg: &'static str| {
+            Error::new(ErrorKind::ConfigInvalid, msg).with_context("endpoint", server)
+        };
+
+        let (host, port) = if let Some(rest) = server.strip_prefix('[') {
+            let (host, port) = rest
+                .split_once(']')
+                .ok_or_else(|| invalid("endpoint has unclosed bracket in ipv6 address"))?;
+            if host.parse::<std::net::Ipv6Addr>().is_err() {
+                return Err(invalid("endpoint has invalid ipv6 address"));
+            }
+            let port = port
+                .strip_prefix(':')
+                .ok_or_else(|| invalid("endpoint is missing port, expected host:port"))?;
+            (host, port)
+        } else {
+            let (host, port) = server
+                .rsplit_once(':')
+                .ok_or_else(|| invalid("endpoint is missing port, expected host:port"))?;
+            let valid_host = host
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'));
+            if host.is_empty() || !valid_host {
+                return Err(invalid("endpoint has invalid host"));
+            }
+            (host, port)
+        };
+        match port.parse::<u16>() {
+            Ok(port) if port != 0 => {}
+            _ => {
+                return Err(invalid("endpoint has invalid port, expected 1-65535")
+                    .with_context("host", host)
+                    .with_context("port", port))
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Check that all endpoints of the connect string are reachable.
+async fn check_endpoints(endpoint: &str) -> Result<()> {
+    // Strip the chroot path, like `host1:2181,host2:2181/app`.
+    let hosts = match endpoint.find('/') {
+        Some(idx) => &endpoint[..idx],
+        None => endpoint,
+    };
+    let probes = hosts
+        .split(',')
+        .map(|v| v.trim())
+        .filter(|v| !v.is_empty())
+        .map(|host| async move {
+            // Ports are required by `check_endpoint_format` at build.
+            let reachable = matches!(
+                tokio::time::timeout(ENDPOINT_CHECK_TIMEOUT, tokio::net::TcpStream::connect(host))
+                    .await,
+                Ok(Ok(_))
+            );
+            (host, reachable)
+        });
+
+    let failed: Vec<&str> = futures::future::join_all(probes)
+        .await
+        .into_iter()
+        .filter(|(_, reachable)| !reachable)
+        .map(|(host, _)| host)
+        .collect();
+    if !failed.is_empty() {
+        return Err(Error::new(
+            ErrorKind::Unexpected,
+            "not all zookeeper endpoints are reachable",
+        )
+        .with_context("endpoint", endpoint)
+        .with_context("failed_endpoints", failed.join(",")));
+    }
+    Ok(())
+}
+
+/// Slice the range of value, out of bound range will be truncated.
+fn slice_range(value: &[u8], range: BytesRange) -> &[u8] {
+    let len = value.len() as u64;
+    let (start, end) = match (range.offset(), range.size()) {
+        (Some(offset), Some(size)) => (offset, offset.saturating_add(size)),
+        (Some(offset), None) => (offset, len),
+        (None, Some(size)) => (len.saturating_sub(size), len),
+        (None, None) => (0, len),
+    };
+    let start = start.min(len) as usize;
+    let end = end.min(len) as usize;
+    &value[start..end]
+}
+
+/// Join the child name to the absolute parent path.
+pub(super) fn join_zookeeper_path(parent: &str, child: &str) -> String {
+    if parent == "/" {
+        format!("/{child}")
+    } else {
+        format!("{parent}/{child}")
+    }
+}
+
+fn parse_stat_metadata(stat: &zk::Stat) -> Result<Metadata> {
+    let mode = if stat.num_children > 0 {
+        EntryMode::DIR
+    } else {
+        EntryMode::FILE
+    };
+    let mut meta = Metadata::new(mode).with_content_length(stat.data_length as u64);
+    meta.set_last_modified(parse_zookeeper_mtime(stat.mtime)?);
+    meta.set_etag(&format_etag(stat));
+    Ok(meta)
+}
+
+/// Format the etag of a node from its stat.
+///
+/// The `version` of a node restarts from 0 once it's deleted and created
+/// again, so `czxid` is included to tell apart the incarnations.
+fn format_etag(stat: &zk::Stat) -> String {
+    format!("\"{:x}-{}\"", stat.czxid, stat.version)
+}
+
+/// Parse the etag formatted by [`format_etag`] back into `czxid` and
+/// `version`.
+fn parse_etag(etag: &str) -> Option<(i64, i32)> {
+    let etag = etag.strip_prefix('"')?.strip_suffix('"')?;
+    let (czxid, version) = etag.split_once('-')?;
+    Some((i64::from_str_radix(czxid, 16).ok()?, version.parse().ok()?))
+}
+
+/// Convert zookeeper's `mtime`, which is milliseconds since unix epoch, into
+/// a `DateTime<Utc>`.
+fn parse_zookeeper_mtime(mtime: i64) -> Result<DateTime<Utc>> {
+    let secs = mtime.div_euclid(1000);
+    let nsecs = (mtime.rem_euclid(1000) * 1_000_000) as u32;
+    Utc.timestamp_opt(secs, nsecs).single().ok_or_else(|| {
+        Error::new(ErrorKind::Unexpected, "zookeeper mtime is out of range")
+            .with_context("mtime", mtime.to_string())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raw::adapters::kv::Adapter;
+    use crate::raw::Accessor;
+
+    #[test]
+    fn test_hostname_endpoint_is_not_resolved() {
+        let backend = ZookeeperBuilder::default()
+            .endpoint("zookeeper.example.com:2181")
+            .build()
+            .unwrap();
+        assert_eq!(backend.adapter().endpoint, "zookeeper.example.com:2181");
+    }
+
+    #[test]
+    fn test_multiple_endpoints() {
+        let backend = ZookeeperBuilder::default()
+            .endpoints(vec![
+                "zk1:2181".to_string(),
+                " zk2:2181".to_string(),
+                "".to_string(),
+            ])
+            .build()
+            .unwrap();
+        assert_eq!(backend.adapter().endpoint, "zk1:2181,zk2:2181");
+
+        let map = HashMap::from([(
+            "endpoint".to_string(),
+            "zk1:2181, zk2:2181,,zk3:2181/app".to_string(),
+        )]);
+        let backend = ZookeeperBuilder::from_map(map).build().unwrap();
+        assert_eq!(backend.adapter().endpoint, "zk1:2181,zk2:2181,zk3:2181/app");
+    }
+
+    #[test]
+    fn test_endpoint_format() {
+        let backend = ZookeeperBuilder::default().build().unwrap();
+        assert_eq!(backend.adapter().endpoint, DEFAULT_ZOOKEEPER_ENDPOINT);
+
+        for endpoint in ["zk-1.example.com:2181,127.0.0.1:2182/app", "[::1]:2181"] {
+            let backend = ZookeeperBuilder::default()
+                .endpoint(endpoint)
+                .build()
+                .unwrap();
+            assert_eq!(backend.adapter().endpoint, endpoint);
+        }
+
+        let cases = [
+            ("127.0.0.1;2181", "missing port"),
+            ("127.0.0.1", "missing port"),
+            ("zk1:2181,zk2", "missing port"),
+            ("zk1:", "invalid port"),
+            ("zk1:65536", "invalid port"),
+            ("zk1:0", "invalid port"),
+            (":2181", "invalid host"),
+            ("zk;1:2181", "invalid host"),
+            ("[::1:2181", "unclosed bracket"),
+            ("[::g]:2181", "invalid ipv6"),
+            ("[::1]", "missing port"),
+            (",/app", "doesn't contain any server"),
+        ];
+        for (endpoint, msg) in cases {
+            let map = HashMap::from([("endpoint".to_string(), endpoint.to_string())]);
+            let err = ZookeeperBuilder::from_map(map).build().unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::ConfigInvalid, "{endpoint}");
+            assert!(format!("{err}").contains(msg), "{endpoint}: {err}");
+        }
+    }
+
+    #[test]
+    fn test_pool_size() {
+        let backend = ZookeeperBuilder::default().build().unwrap();
+        assert_eq!(backend.adapter().clients.len(), 1);
+
+        let backend = ZookeeperBuilder::default().pool_size(4).build().unwrap();
+        assert_eq!(backend.adapter().clients.len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_clones_share_connection() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = listener.local_addr().unwrap().to_string();
+        let accepted = Arc::new(AtomicUsize::new(0));
+        let counter = accepted.clone();
+        // Accept connections without ever replying, so connecting hangs.
+        let server = tokio::spawn(async move {
+            let mut streams = Vec::new();
+            while let Ok((stream, _)) = listener.accept().await {
+                counter.fetch_add(1, Ordering::SeqCst);
+                streams.push(stream);
+            }
+        });
+
+        let backend = ZookeeperBuilder::default()
+            .endpoint(&endpoint)
+            .build()
+            .unwrap();
+        let adapters: Vec<ZkAdapter> = (0..4).map(|_| backend.adapter().clone()).collect();
+        assert!(Arc::ptr_eq(&adapters[0].clients, &adapters[3].clients));
+
+        let connects = adapters.iter().map(|adapter| adapter.get_connection());
+        let res = tokio::time::timeout(
+            Duration::from_millis(500),
+            futures::future::join_all(connects),
+        )
+        .await;
+        assert!(res.is_err(), "connecting to a silent server must hang");
+        assert_eq!(accepted.load(Ordering::SeqCst), 1);
+        server.abort();
+    }
+
+    #[tokio::test]
+    async fn test_connect_timeout() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = listener.local_addr().unwrap().to_string();
+
+        let backend = ZookeeperBuilder::default()
+            .endpoint(&endpoint)
+            .connect_timeout(Duration::from_millis(100))
+            .build()
+            .unwrap();
+        let err = tokio::time::timeout(Duration::from_secs(5), backend.adapter().get_connection())
+            .await
+            .expect("connect must time out by connect_timeout")
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Unexpected);
+        assert!(err.is_temporary());
+        drop(listener);
+    }
+
+    #[tokio::test]
+    async fn test_blocking_in_runtime() {
+        let backend = ZookeeperBuilder::default().build().unwrap();
+        assert!(backend.info().native_capability().blocking);
+
+        let err = backend.adapter().blocking_get("a").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Unsupported);
+        let err = backend.adapter().blocking_rename("a", "b").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Unsupported);
+    }
+
+    #[test]
+    fn test_blocking_on_owned_runtime() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = listener.local_addr().unwrap().to_string();
+
+        let backend = ZookeeperBuilder::default()
+            .endpoint(&endpoint)
+            .connect_timeout(Duration::from_millis(100))
+            .build()
+            .unwrap();
+        assert!(backend.info().native_capability().blocking);
+        // The write reaches the connect on the owned runtime and times out
+        // there.
+        let err = backend.adapter().blocking_set("a/b/c", b"v").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Unexpected);
+        assert!(err.is_temporary());
+        drop(listener);
+
+        // Dropping the started runtime in an async context must not panic.
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async move { drop(backend) });
+    }
+
+    #[tokio::test]
+    async fn test_health_check_unreachable() {
+        // Bind and drop to get a port that nobody listens on.
+        let endpoint = {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            listener.local_addr().unwrap().to_string()
+        };
+
+        let backend = ZookeeperBuilder::default()
+            .endpoint(&endpoint)
+            .connect_timeout(Duration::from_millis(100))
+            .build()
+            .unwrap();
+        let err = backend.adapter().health_check().await.unwrap_err();
+        assert!(err.is_temporary(), "{err}");
+        assert_eq!(backend.adapter().stats().ops.get("health_check"), Some(&1));
+
+        let adapter = backend.adapter();
+        assert!(adapter.get_prefix("a", 1).await.is_err());
+        assert_eq!(adapter.stats().ops.get("get_prefix"), Some(&1));
+        assert!(adapter.next_id("counter").await.is_err());
+        assert_eq!(adapter.stats().ops.get("next_id"), Some(&1));
+        assert!(adapter.swap("a", "b").await.is_err());
+        assert_eq!(adapter.stats().ops.get("swap"), Some(&1));
+        assert!(adapter.diff("/", HashMap::new()).await.is_err());
+        assert_eq!(adapter.stats().ops.get("diff"), Some(&1));
+        assert!(adapter.scan_with_metadata("a").await.is_err());
+        assert_eq!(adapter.stats().ops.get("scan_with_metadata"), Some(&1));
+        assert!(adapter.set_subtree("a", &["b"], b"v", true).await.is_err());
+        assert_eq!(adapter.stats().ops.get("set_subtree"), Some(&1));
+        let ops = vec![ZookeeperBatchOp::Delete {
+            path: "a".to_string(),
+        }];
+        assert!(adapter.batch(ops).await.is_err());
+        assert_eq!(adapter.stats().ops.get("batch"), Some(&1));
+    }
+
+    #[test]
+    fn test_proxy() {
+        let map = HashMap::from([("proxy".to_string(), "socks5://proxy:1080".to_string())]);
+        let backend = ZookeeperBuilder::from_map(map).build().unwrap();
+        assert_eq!(
+            backend.adapter().proxy,
+            Some(ZookeeperProxy::Socks5("proxy:1080".to_string()))
+        );
+
+        let err = ZookeeperBuilder::default()
+            .proxy("proxy:1080")
+            .build()
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ConfigInvalid);
+    }
+
+    #[tokio::test]
+    async fn test_connect_through_rejecting_proxy() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy = listener.local_addr().unwrap().to_string();
+        // Reject every CONNECT.
+        let server = tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                use tokio::io::AsyncWriteExt;
+                let _ = stream.write_all(b"HTTP/1.1 403 Forbidden\r\n\r\n").await;
+            }
+        });
+
+        let backend = ZookeeperBuilder::default()
+            .endpoint("zk1:2181")
+            .proxy(&format!("http://{proxy}"))
+            .connect_timeout(Duration::from_millis(500))
+            .build()
+            .unwrap();
+        let err = backend.adapter().get_connection().await.unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Unexpected);
+        assert!(err.is_temporary());
+        assert!(format!("{err}").contains("through proxy"), "{err}");
+        server.abort();
+    }
+
+    #[tokio::test]
+    async fn test_check_endpoints() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let reachable = listener.local_addr().unwrap().to_string();
+        // Bind and drop to get a port that nobody listens on.
+        let unreachable = {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            listener.local_addr().unwrap().to_string()
+        };
+
+        assert!(check_endpoints(&reachable).await.is_ok());
+        assert!(check_endpoints(&format!("{reachable}/chroot"))
+            .await
+            .is_ok());
+
+        let endpoint = format!("{reachable},{unreachable}/chroot");
+        let err = check_endpoints(&endpoint).await.unwrap_err();
+        let msg = format!("{err}");
+        assert!(
+            msg.contains(&format!("failed_endpoints: {unreachable}")),
+            "{msg}"
+        );
+        assert!(
+            !msg.contains(&format!("failed_endpoints: {reachable}")),
+            "{msg}"
+        );
+    }
+
+    #[test]
+    fn test_password_file() {
+        let path = std::env::temp_dir().join(format!(
+            "opendal-zookeeper-password-{}",
+            uuid::Uuid::new_v4()
+        ));
+        std::fs::write(&path, "secret\n").unwrap();
+
+        let backend = ZookeeperBuilder::default()
+            .username("user")
+            .password_file(path.to_str().unwrap())
+            .build()
+            .unwrap();
+        assert_eq!(backend.adapter().auths[0].1, b"user:secret");
+
+        let err = ZookeeperBuilder::default()
+            .password("pass")
+            .password_file(path.to_str().unwrap())
+            .build()
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ConfigInvalid);
+
+        std::fs::remove_file(&path).unwrap();
+        let err = ZookeeperBuilder::default()
+            .username("user")
+            .password_file(path.to_str().unwrap())
+            .build()
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ConfigInvalid);
+    }
+
+    #[test]
+    fn test_parse_permission() {
+        assert_eq!(parse_permission("all"), Some(zk::Permission::ALL));
+        assert_eq!(
+            parse_permission("rw"),
+            Some(zk::Permission::READ | zk::Permission::WRITE)
+        );
+        assert_eq!(parse_permission("rwcda"), Some(zk::Permission::ALL));
+        assert_eq!(parse_permission(""), None);
+        assert_eq!(parse_permission("rx"), None);
+    }
+
+    #[test]
+    fn test_acl() {
+        let backend = ZookeeperBuilder::default()
+            .username("user")
+            .password("pass")
+            .build()
+            .unwrap();
+        assert_eq!(backend.adapter().acl.len(), 1);
+        assert_eq!(backend.adapter().acl[0].scheme(), "auth");
+
+        let mut map = HashMap::new();
+        map.insert(
+            "acl".to_string(),
+            "digest:user:hash:all,world:anyone:r".to_string(),
+        );
+        let backend = ZookeeperBuilder::from_map(map).build().unwrap();
+        let acl = &backend.adapter().acl;
+        assert_eq!(acl.len(), 2);
+        assert_eq!((acl[0].scheme(), acl[0].id()), ("digest", "user:hash"));
+        assert_eq!(acl[0].permission(), zk::Permission::ALL);
+        assert_eq!((acl[1].scheme(), acl[1].id()), ("world", "anyone"));
+        assert_eq!(acl[1].permission(), zk::Permission::READ);
+
+        let err = ZookeeperBuilder::default()
+            .acl("world", "anyone", "rx")
+            .build()
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ConfigInvalid);
+    }
+
+    #[test]
+    fn test_root() {
+        let backend = ZookeeperBuilder::default().build().unwrap();
+        assert_eq!(backend.info().root(), "/");
+
+        let backend = ZookeeperBuilder::default()
+            .root("myapp/opendal")
+            .build()
+            .unwrap();
+        assert_eq!(backend.info().root(), "/myapp/opendal/");
+        assert_eq!(backend.adapter().root, "/myapp/opendal");
+    }
+
+    #[test]
+    fn test_verify_auth() {
+        let err = ZookeeperBuilder::default()
+            .verify_auth()
+            .build()
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ConfigInvalid);
+
+        let backend = ZookeeperBuilder::default()
+            .verify_auth()
+            .auth("digest", "user:pass")
+            .build()
+            .unwrap();
+        assert!(backend.adapter().verify_auth);
+        assert_eq!(backend.adapter().root, "/");
+    }
+
+    #[test]
+    fn test_session_timeout() {
+        let backend = ZookeeperBuilder::default()
+            .session_timeout(Duration::from_secs(10))
+            .build()
+            .unwrap();
+        assert_eq!(
+            backend.adapter().session_timeout,
+            Some(Duration::from_secs(10))
+        );
+
+        for secs in [1, 60] {
+            let err = ZookeeperBuilder::default()
+                .session_timeout(Duration::from_secs(secs))
+                .build()
+                .unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::ConfigInvalid);
+        }
+    }
+
+    #[test]
+    fn test_auth_scheme() {
+        let backend = ZookeeperBuilder::default()
+            .auth_scheme("digest")
+            .username("user")
+            .password("pass")
+            .build()
+            .unwrap();
+        assert_eq!(backend.adapter().auths[0].0, "digest");
+
+        let err = ZookeeperBuilder::default()
+            .auth_scheme("digest")
+            .build()
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ConfigInvalid);
+
+        for scheme in ["sasl", "custom"] {
+            let map = HashMap::from([
+                ("auth_scheme".to_string(), scheme.to_string()),
+                ("username".to_string(), "user".to_string()),
+                ("password".to_string(), "pass".to_string()),
+            ]);
+            let err = ZookeeperBuilder::from_map(map).build().unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::ConfigInvalid, "{scheme}");
+        }
+    }
+
+    #[test]
+    fn test_missing_list() {
+        let backend = ZookeeperBuilder::default().build().unwrap();
+        assert!(backend.adapter().missing_list("/a").is_ok());
+
+        let backend = ZookeeperBuilder::default().strict_list().build().unwrap();
+        let err = backend.adapter().missing_list("/a").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+    }
+
+    #[tokio::test]
+    async fn test_set_subtree_invalid_paths() {
+        let backend = ZookeeperBuilder::default().build().unwrap();
+        for path in ["b//c", "b/./c", "../b", "b/", "/b"] {
+            let err = backend
+                .adapter()
+                .set_subtree("a", &["ok", path], b"v", true)
+                .await
+                .unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::ConfigInvalid, "{path}: {err}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_create_sequential_invalid_path() {
+        let backend = ZookeeperBuilder::default().build().unwrap();
+        for path in ["a//n", "a/../n", "a/\u{0}n"] {
+            let err = backend
+                .adapter()
+                .create_sequential(path, b"v", false, None)
+                .await
+                .unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::ConfigInvalid, "{path}: {err}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_read_only() {
+        let backend = ZookeeperBuilder::default().build().unwrap();
+        let cap = backend.info().native_capability();
+        assert!(cap.write && cap.delete && cap.rename);
+        assert!(cap.read_with_if_none_match);
+
+        let map = HashMap::from([("read_only".to_string(), "true".to_string())]);
+        let backend = ZookeeperBuilder::from_map(map).build().unwrap();
+        let cap = backend.info().native_capability();
+        assert!(cap.read && cap.stat && cap.list);
+        assert!(!cap.write && !cap.create_dir && !cap.delete);
+        assert!(!cap.copy && !cap.rename && !cap.batch);
+
+        // Mutating helpers are rejected before connecting.
+        let adapter = backend.adapter();
+        let errs = vec![
+            adapter.set("a", b"v").await.unwrap_err(),
+            adapter.delete("a").await.unwrap_err(),
+            adapter.rename("a", "b").await.unwrap_err(),
+            adapter.copy("a", "b").await.unwrap_err(),
+            adapter.next_id("counter").await.unwrap_err(),
+            adapter
+                .create_sequential("a/n", b"v", false, None)
+                .await
+                .unwrap_err(),
+            adapter
+                .set_subtree("a", &["b"], b"v", true)
+                .await
+                .unwrap_err(),
+            adapter
+                .batch(vec![ZookeeperBatchOp::Delete {
+                    path: "a".to_string(),
+                }])
+                .await
+                .unwrap_err(),
+            adapter.swap("a", "b").await.unwrap_err(),
+            adapter.set_if_version("a", b"v", 0).await.unwrap_err(),
+            adapter.set_if_match("a", b"v", "1-0").await.unwrap_err(),
+        ];
+        for err in errs {
+            assert_eq!(err.kind(), ErrorKind::Unsupported, "{err}");
+        }
+    }
+
+    #[test]
+    fn test_store_metadata() {
+        let backend = ZookeeperBuilder::default().build().unwrap();
+        let cap = backend.info().native_capability();
+        assert!(!cap.write_with_content_type && !cap.write_with_cache_control);
+        let adapter = backend.adapter();
+        assert_eq!(adapter.encode_value(b"\0ZKM").unwrap(), b"\0ZKM");
+        assert_eq!(adapter.decode_value(b"\0ZKM".to_vec()).unwrap(), b"\0ZKM");
+
+        let map = HashMap::from([("store_metadata".to_string(), "true".to_string())]);
+        let backend = ZookeeperBuilder::from_map(map).build().unwrap();
+        let cap = backend.info().native_capability();
+        assert!(cap.write_with_content_type && cap.write_with_content_disposition);
+        assert!(cap.write_with_cache_control);
+
+        let adapter = backend.adapter();
+        let args = OpWrite::default().with_content_type("text/plain");
+        let encoded = adapter
+            .encode_value_with(b"hello", &ValueHeader::from_op(&args))
+            .unwrap();
+        assert_ne!(encoded, b"hello");
+        let (header, value) = adapter.decode_value_with(encoded).unwrap();
+        assert_eq!(header.content_type.as_deref(), Some("text/plain"));
+        assert_eq!(value, b"hello");
+        // Values written before enabling it are read as is.
+        assert_eq!(adapter.decode_value(b"hello".to_vec()).unwrap(), b"hello");
+
+        let mut builder = ZookeeperBuilder::default();
+        builder.store_metadata().read_only();
+        let cap = builder.build().unwrap().info().native_capability();
+        assert!(!cap.write_with_content_type);
+    }
+
+    #[test]
+    fn test_invalid_config_from_map() {
+        let cases = [
+            ("pool_size", ""),
+            ("max_children_warn", "many"),
+            ("max_children_error", "1.5"),
+            ("durability", "all"),
+            ("session_timeout", "10s"),
+            ("create_mode", "persist"),
+            ("max_node_size", "1MB"),
+            ("connect_timeout", "-1"),
+            ("cache_ttl", "ten"),
+            ("cache_capacity", "-1"),
+        ];
+        for (key, value) in cases {
+            let map = HashMap::from([(key.to_string(), value.to_string())]);
+            let err = ZookeeperBuilder::from_map(map).build().unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::ConfigInvalid, "{key}");
+            assert!(format!("{err}").contains(&format!("key: {key}")), "{err}");
+        }
+
+        for key in ["enable_tls", "ca_cert", "client_cert", "client_key"] {
+            let map = HashMap::from([(key.to_string(), "true".to_string())]);
+            let err = ZookeeperBuilder::from_map(map).build().unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::ConfigInvalid, "{key}");
+            assert!(format!("{err}").contains("tls is not supported"), "{err}");
+        }
+
+        #[cfg(feature = "services-zookeeper-encryption")]
+        {
+            let map = HashMap::from([("encryption_key".to_string(), "not base64!".to_string())]);
+            let err = ZookeeperBuilder::from_map(map).build().unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::ConfigInvalid);
+            assert!(format!("{err}").contains("key: encryption_key"), "{err}");
+        }
+    }
+
+    #[test]
+    fn test_durability_from_map() {
+        let backend = ZookeeperBuilder::from_map(HashMap::new()).build().unwrap();
+        assert_eq!(backend.adapter().durability, ZookeeperDurability::QuorumAck);
+
+        let map = HashMap::from([("durability".to_string(), "local_visible".to_string())]);
+        let backend = ZookeeperBuilder::from_map(map).build().unwrap();
+        assert_eq!(
+            backend.adapter().durability,
+            ZookeeperDurability::LocalVisible
+        );
+    }
+
+    #[test]
+    fn test_max_node_size() {
+        let backend = ZookeeperBuilder::default().build().unwrap();
+        assert!(backend.adapter().encode_value(&[0; 1024 * 1024]).is_ok());
+        assert!(backend
+            .adapter()
+            .encode_value(&[0; 1024 * 1024 + 1])
+            .is_err());
+
+        let backend = ZookeeperBuilder::default()
+            .max_node_size(4)
+            .build()
+            .unwrap();
+        assert!(backend.adapter().encode_value(b"1234").is_ok());
+        let err = backend.adapter().encode_value(b"12345").unwrap_err();
+        assert!(format!("{err}").contains("max_node_size: 4"), "{err}");
+    }
+
+    #[test]
+    fn test_create_mode() {
+        let mut map = HashMap::new();
+        map.insert("create_mode".to_string(), "ephemeral".to_string());
+        let backend = ZookeeperBuilder::from_map(map).build().unwrap();
+        assert_eq!(
+            backend.adapter().node_create_mode(true),
+            zk::CreateMode::Ephemeral
+        );
+        assert_eq!(
+            backend.adapter().node_create_mode(false),
+            zk::CreateMode::Persistent
+        );
+
+        let backend = ZookeeperBuilder::default().build().unwrap();
+        assert_eq!(
+            backend.adapter().node_create_mode(true),
+            zk::CreateMode::Persistent
+        );
+    }
+
+    #[test]
+    fn test_multiple_auths() {
+        let mut map = HashMap::new();
+        map.insert("username".to_string(), "foo".to_string());
+        map.insert("password".to_string(), "bar".to_string());
+        map.insert(
+            "auths".to_string(),
+            "digest:baz:qux,ip:10.0.0.1".to_string(),
+        );
+        let backend = ZookeeperBuilder::from_map(map).build().unwrap();
+        let auths: Vec<_> = backend
+            .adapter()
+            .auths
+            .iter()
+            .map(|(scheme, auth)| (scheme.as_str(), String::from_utf8_lossy(auth).to_string()))
+            .collect();
+        assert_eq!(
+            auths,
+            vec![
+                ("digest", "foo:bar".to_string()),
+                ("digest", "baz:qux".to_string()),
+                ("ip", "10.0.0.1".to_string()),
+            ]
+        );
+
+        let backend = ZookeeperBuilder::default()
+            .auths(vec![
+                ("digest".to_string(), "a:b".to_string()),
+                ("digest".to_string(), "c:d".to_string()),
+            ])
+            .build()
+            .unwrap();
+        assert_eq!(backend.adapter().auths.len(), 2);
+        assert_eq!(backend.adapter().acl.len(), 1);
+        assert_eq!(backend.adapter().acl[0].scheme(), "auth");
+
+        let err = ZookeeperBuilder::default()
+            .auth("digest", "")
+            .build()
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ConfigInvalid);
+    }
+
+    #[test]
+    fn test_slice_range() {
+        let value = b"hello world";
+        let cases = vec![
+            (BytesRange::new(None, None), &b"hello world"[..]),
+            (BytesRange::new(Some(6), None), b"world"),
+            (BytesRange::new(Some(0), Some(5)), b"hello"),
+            (BytesRange::new(None, Some(5)), b"world"),
+            (BytesRange::new(Some(6), Some(100)), b"world"),
+            (BytesRange::new(Some(100), None), b""),
+            (BytesRange::new(None, Some(100)), b"hello world"),
+        ];
+        for (range, expected) in cases {
+            assert_eq!(slice_range(value, range), expected, "{range:?}");
+        }
+    }
+
+    #[test]
+    fn test_plan_batch_seed_tree() {
+        let ops = HashMap::from([
+            ("/a/b/c".to_string(), Some(b"c".to_vec())),
+            ("/a/b".to_string(), Some(b"b".to_vec())),
+            ("/a/d".to_string(), Some(b"d".to_vec())),
+            ("/x/y".to_string(), Some(b"y".to_vec())),
+        ]);
+        let existing = HashSet::from(["/x".to_string()]);
+
+        assert_eq!(
+            plan_batch(&ops, &existing),
+            vec![
+                PlannedOp::Create("/a".to_string(), vec![]),
+                PlannedOp::Create("/a/b".to_string(), b"b".to_vec()),
+                PlannedOp::Create("/a/d".to_string(), b"d".to_vec()),
+                PlannedOp::Create("/x/y".to_string(), b"y".to_vec()),
+                PlannedOp::Create("/a/b/c".to_string(), b"c".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_plan_batch_delete_tree() {
+        let ops = HashMap::from([
+            ("/a".to_string(), None),
+            ("/a/b/c".to_string(), None),
+            ("/a/b".to_string(), None),
+            ("/a/missing".to_string(), None),
+            ("/x".to_string(), Some(b"x".to_vec())),
+        ]);
+        let existing = HashSet::from([
+            "/a".to_string(),
+            "/a/b".to_string(),
+            "/a/b/c".to_string(),
+            "/x".to_string(),
+        ]);
+
+        assert_eq!(
+            plan_batch(&ops, &existing),
+            vec![
+                PlannedOp::SetData("/x".to_string(), b"x".to_vec()),
+                PlannedOp::Delete("/a/b/c".to_string()),
+                PlannedOp::Delete("/a/b".to_string()),
+                PlannedOp::Delete("/a".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_is_session_lost() {
+        assert!(is_session_lost(&parse_zookeeper_error(
+            zk::Error::SessionExpired
+        )));
+        assert!(is_session_lost(&parse_zookeeper_error(
+            zk::Error::ConnectionLoss
+        )));
+        assert!(!is_session_lost(&parse_zookeeper_error(zk::Error::NoNode)));
+        assert!(!is_session_lost(&Error::new(ErrorKind::Unexpected, "")));
+    }
+
+    #[test]
+    fn test_scan_key() {
+        assert_eq!(scan_key("/a", false), "a");
+        assert_eq!(scan_key("/a/b", true), "a/b/");
+    }
+
+    #[test]
+    fn test_parent_path() {
+        assert_eq!(parent_path("/"), "/");
+        assert_eq!(parent_path("/a"), "/");
+        assert_eq!(parent_path("/a/b"), "/a");
+        assert_eq!(parent_path("/a/b/c"), "/a/b");
+    }
+
+    #[test]
+    fn test_parse_zookeeper_mtime() {
+        let t = parse_zookeeper_mtime(1_700_000_000_123).unwrap();
+        assert_eq!(t.to_rfc3339(), "2023-11-14T22:13:20.123+00:00");
+        assert_eq!(t.timestamp(), 1_700_000_000);
+        assert_eq!(t.timestamp_subsec_millis(), 123);
+
+        let t = parse_zookeeper_mtime(0).unwrap();
+        assert_eq!(t.to_rfc3339(), "1970-01-01T00:00:00+00:00");
+
+        let t = parse_zookeeper_mtime(-1).unwrap();
+        assert_eq!(t.to_rfc3339(), "1969-12-31T23:59:59.999+00:00");
+    }
+
+    #[test]
+    fn test_parse_stat_metadata() {
+        let stat = zk::Stat {
+            czxid: 1,
+            mzxid: 2,
+            pzxid: 2,
+            ctime: 1_700_000_000_000,
+            mtime: 1_700_000_000_123,
+            version: 3,
+            cversion: 0,
+            aversion: 0,
+            ephemeral_owner: 0,
+            data_length: 42,
+            num_children: 0,
+        };
+        let meta = parse_stat_metadata(&stat).unwrap();
+        assert_eq!(meta.mode(), EntryMode::FILE);
+        assert_eq!(meta.content_length(), 42);
+        assert_eq!(
+            meta.last_modified(),
+            Some(Utc.timestamp_millis_opt(1_700_000_000_123).unwrap())
+        );
+        assert_eq!(meta.etag(), Some("\"1-3\""));
+    }
+
+    /// A fake zookeeper tree, that records created nodes with whether they
+    /// are created as leaf and their data.
+    #[derive(Default)]
+    struct FakeTree(Mutex<BTreeMap<String, (bool, Vec<u8>)>>);
+
+    impl FakeTree {
+        fn with_nodes(nodes: &[&str]) -> Self {
+            let tree = Self::default();
+            for node in nodes {
+                tree.0.lock().insert(node.to_string(), (false, Vec::new()));
+            }
+            tree
+        }
+
+        async fn create(
+            &self,
+            path: String,
+            data: &[u8],
+            is_leaf: bool,
+        ) -> std::result::Result<(), zk::Error> {
+            let mut nodes = self.0.lock();
+            if nodes.contains_key(&path) {
+                return Err(zk::Error::NodeExists);
+            }
+            let parent = parent_path(&path);
+            if parent != "/" && !nodes.contains_key(parent) {
+                return Err(zk::Error::NoNode);
+            }
+            nodes.insert(path, (is_leaf, data.to_vec()));
+            Ok(())
+        }
+
+        fn nodes(&self) -> Vec<(String, bool)> {
+            self.0
+                .lock()
+                .iter()
+                .map(|(k, (is_leaf, _))| (k.clone(), *is_leaf))
+                .collect()
+        }
+
+        fn data(&self, path: &str) -> Vec<u8> {
+            self.0.lock()[path].1.clone()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_create_with_ancestors_deep_path() {
+        let tree = FakeTree::default();
+        create_with_ancestors(
+            "/a/b/c/d",
+            b"v",
+            &KnownNodes::default(),
+            |node, data, is_leaf| tree.create(node, data, is_leaf),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            tree.nodes(),
+            vec![
+                ("/a".to_string(), false),
+                ("/a/b".to_string(), false),
+                ("/a/b/c".to_string(), false),
+                ("/a/b/c/d".to_string(), true),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_create_with_ancestors_existing_ancestors() {
+        let tree = FakeTree::with_nodes(&["/a", "/a/b"]);
+        create_with_ancestors(
+            "a//b/c/d/",
+            b"v",
+            &KnownNodes::default(),
+            |node, data, is_leaf| tree.create(node, data, is_leaf),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            tree.nodes(),
+            vec![
+                ("/a".to_string(), false),
+                ("/a/b".to_string(), false),
+                ("/a/b/c".to_string(), false),
+                ("/a/b/c/d".to_string(), true),
+            ]
+        );
+
+        let err = create_with_ancestors(
+            "/a/b/c/d",
+            b"v",
+            &KnownNodes::default(),
+            |node, data, is_leaf| tree.create(node, data, is_leaf),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err, zk::Error::NodeExists);
+    }
+
+    #[tokio::test]
+    async fn test_create_with_ancestors_empty_ancestor_data() {
+        let tree = FakeTree::with_nodes(&["/a"]);
+        create_with_ancestors(
+            "/a/b/c",
+            b"value",
+            &KnownNodes::default(),
+            |node, data, is_leaf| tree.create(node, data, is_leaf),
+        )
+        .await
+        .unwrap();
+        assert_eq!(tree.data("/a"), b"");
+        assert_eq!(tree.data("/a/b"), b"");
+        assert_eq!(tree.data("/a/b/c"), b"value");
+    }
+
+    #[tokio::test]
+    async fn test_create_with_ancestors_known_nodes() {
+        let tree = FakeTree::default();
+        let known = KnownNodes::default();
+        create_with_ancestors("/a/b/c/x", b"v", &known, |node, data, is_leaf| {
+            tree.create(node, data, is_leaf)
+        })
+        .await
+        .unwrap();
+        assert!(known.contains("/a") && known.contains("/a/b/c"));
+
+        // Only the missing /a/b/c/d and leaf are created.
+        let calls = AtomicUsize::new(0);
+        create_with_ancestors("/a/b/c/d/y", b"v", &known, |node, data, is_leaf| {
+            calls.fetch_add(1, Ordering::SeqCst);
+            tree.create(node, data, is_leaf)
+        })
+        .await
+        .unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+        assert!(known.contains("/a/b/c/d"));
+
+        known.remove_subtree("/a/b");
+        assert!(known.contains("/a"));
+        assert!(!known.contains("/a/b") && !known.contains("/a/b/c/d"));
+    }
+
+    #[tokio::test]
+    async fn test_create_with_ancestors_stale_known_nodes() {
+        let tree = FakeTree::with_nodes(&["/a"]);
+        let known = KnownNodes::default();
+        known.insert("/a/b");
+        create_with_ancestors("/a/b/c/x", b"v", &known, |node, data, is_leaf| {
+            tree.create(node, data, is_leaf)
+        })
+        .await
+        .unwrap();
+        assert_eq!(tree.data("/a/b/c/x"), b"v");
+        assert!(known.contains("/a/b") && known.contains("/a/b/c"));
+    }
+
+    #[tokio::test]
+    async fn test_create_with_ancestors_root_level() {
+        let tree = FakeTree::default();
+        create_with_ancestors("/a", b"v", &KnownNodes::default(), |node, data, is_leaf| {
+            tree.create(node, data, is_leaf)
+        })
+        .await
+        .unwrap();
+        assert_eq!(tree.nodes(), vec![("/a".to_string(), true)]);
+    }
+
+    #[test]
+    fn test_normalize_zk_path() {
+        let cases = vec![
+            ("", "/"),
+            ("/", "/"),
+            ("a", "/a"),
+            ("a/b/", "/a/b"),
+            ("/a/b", "/a/b"),
+            ("a/.b/c..", "/a/.b/c.."),
+            ("配置/值", "/配置/值"),
+        ];
+        for (path, expected) in cases {
+            assert_eq!(normalize_zk_path(path).unwrap(), expected, "{path}");
+        }
+
+        for path in [
+            "a//b",
+            "a/b//",
+            "a/./b",
+            "../a",
+            "a/\0",
+            "a\u{1}",
+            "a\u{fff5}",
+        ] {
+            let err = normalize_zk_path(path).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::ConfigInvalid, "{path}");
+        }
+    }
+
+    #[test]
+    fn test_parse_etag() {
+        let stat = zk::Stat {
+            czxid: 0x1f00000002,
+            mzxid: 0,
+            pzxid: 0,
+            ctime: 0,
+            mtime: 0,
+            version: 7,
+            cversion: 0,
+            aversion: 0,
+            ephemeral_owner: 0,
+            data_length: 0,
+            num_children: 0,
+        };
+        assert_eq!(parse_etag(&format_etag(&stat)), Some((0x1f00000002, 7)));
+
+        assert_eq!(parse_etag("1-3"), None);
+        assert_eq!(parse_etag("\"1\""), None);
+        assert_eq!(parse_etag("\"x-3\""), None);
+        assert_eq!(parse_etag("\"1-x\""), None);
+    }
 }
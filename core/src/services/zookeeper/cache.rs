@@ -0,0 +1,229 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::time::Duration;
+use std::time::Instant;
+
+use parking_lot::Mutex;
+
+/// ReadCache is a LRU cache for values read from zookeeper.
+///
+/// Entries are evicted in least recently used order once either the number
+/// of entries exceeds `capacity` or the total size of values exceeds
+/// `max_bytes`. Entries older than `ttl` are treated as missing.
+#[derive(Debug)]
+pub struct ReadCache {
+    capacity: Option<usize>,
+    max_bytes: Option<usize>,
+    ttl: Option<Duration>,
+
+    inner: Mutex<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    entries: HashMap<String, Entry>,
+    /// The access tick to key, the smallest tick is the least recently used.
+    order: BTreeMap<u64, String>,
+    tick: u64,
+    bytes: usize,
+}
+
+#[derive(Debug)]
+struct Entry {
+    value: Vec<u8>,
+    inserted: Instant,
+    tick: u64,
+}
+
+impl ReadCache {
+    /// Create a new cache, return `None` if no bound is given.
+    pub fn new(
+        capacity: Option<usize>,
+        max_bytes: Option<usize>,
+        ttl: Option<Duration>,
+    ) -> Option<Self> {
+        if capacity.is_none() && max_bytes.is_none() && ttl.is_none() {
+            return None;
+        }
+
+        Some(Self {
+            capacity,
+            max_bytes,
+            ttl,
+            inner: Mutex::new(Inner::default()),
+        })
+    }
+
+    /// Get the cached value of given path.
+    pub fn get(&self, path: &str) -> Option<Vec<u8>> {
+        self.get_with(path, |value| value.to_vec())
+    }
+
+    /// Visit the cached value of given path without copying the whole value.
+    pub fn get_with<T>(&self, path: &str, f: impl FnOnce(&[u8]) -> T) -> Option<T> {
+        let mut inner = self.inner.lock();
+
+        let expired = match inner.entries.get(path) {
+            None => return None,
+            Some(entry) => self
+                .ttl
+                .map(|ttl| entry.inserted.elapsed() >= ttl)
+                .unwrap_or_default(),
+        };
+        if expired {
+            inner.remove(path);
+            return None;
+        }
+
+        inner.tick += 1;
+        let tick = inner.tick;
+        let entry = inner.entries.get_mut(path).expect("entry must exist");
+        let old = std::mem::replace(&mut entry.tick, tick);
+        let value = f(&entry.value);
+        inner.order.remove(&old);
+        inner.order.insert(tick, path.to_string());
+        Some(value)
+    }
+
+    /// Insert the value of given path into cache, evicting the least
+    /// recently used entries if needed.
+    pub fn insert(&self, path: &str, value: &[u8]) {
+        // Value larger than the whole cache will never fit.
+        if self.max_bytes.map(|v| value.len() > v).unwrap_or_default() {
+            self.remove(path);
+            return;
+        }
+
+        let mut inner = self.inner.lock();
+        inner.remove(path);
+
+        inner.tick += 1;
+        let tick = inner.tick;
+        inner.bytes += value.len();
+        inner.order.insert(tick, path.to_string());
+        inner.entries.insert(
+            path.to_string(),
+            Entry {
+                value: value.to_vec(),
+                inserted: Instant::now(),
+                tick,
+            },
+        );
+
+        while self
+            .capacity
+            .map(|v| inner.entries.len() > v)
+            .unwrap_or_default()
+            || self.max_bytes.map(|v| inner.bytes > v).unwrap_or_default()
+        {
+            let Some(key) = inner.order.values().next().cloned() else {
+                break;
+            };
+            inner.remove(&key);
+        }
+    }
+
+    /// Remove the cached value of given path.
+    pub fn remove(&self, path: &str) {
+        self.inner.lock().remove(path);
+    }
+}
+
+impl Inner {
+    fn remove(&mut self, path: &str) {
+        if let Some(entry) = self.entries.remove(path) {
+            self.order.remove(&entry.tick);
+            self.bytes -= entry.value.len();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_disabled_without_bound() {
+        assert!(ReadCache::new(None, None, None).is_none());
+    }
+
+    #[test]
+    fn test_cache_evict_by_capacity() {
+        let cache = ReadCache::new(Some(2), None, None).unwrap();
+        cache.insert("/a", b"a");
+        cache.insert("/b", b"b");
+        // Touch `/a` so that `/b` becomes the least recently used.
+        assert_eq!(cache.get("/a"), Some(b"a".to_vec()));
+        cache.insert("/c", b"c");
+
+        assert_eq!(cache.get("/a"), Some(b"a".to_vec()));
+        assert_eq!(cache.get("/b"), None);
+        assert_eq!(cache.get("/c"), Some(b"c".to_vec()));
+    }
+
+    #[test]
+    fn test_cache_evict_by_bytes() {
+        let cache = ReadCache::new(None, Some(8), None).unwrap();
+        cache.insert("/a", b"aaaa");
+        cache.insert("/b", b"bbbb");
+        cache.insert("/c", b"cc");
+
+        assert_eq!(cache.get("/a"), None);
+        assert_eq!(cache.get("/b"), Some(b"bbbb".to_vec()));
+        assert_eq!(cache.get("/c"), Some(b"cc".to_vec()));
+
+        // Value larger than max bytes is never cached.
+        cache.insert("/d", b"ddddddddd");
+        assert_eq!(cache.get("/d"), None);
+        assert_eq!(cache.get("/b"), Some(b"bbbb".to_vec()));
+    }
+
+    #[test]
+    fn test_cache_overwrite_and_remove() {
+        let cache = ReadCache::new(None, Some(4), None).unwrap();
+        cache.insert("/a", b"aaaa");
+        cache.insert("/a", b"bb");
+        assert_eq!(cache.get("/a"), Some(b"bb".to_vec()));
+        cache.insert("/b", b"cc");
+        assert_eq!(cache.get("/a"), Some(b"bb".to_vec()));
+
+        cache.remove("/a");
+        assert_eq!(cache.get("/a"), None);
+        assert_eq!(cache.get("/b"), Some(b"cc".to_vec()));
+    }
+
+    #[test]
+    fn test_cache_get_with() {
+        let cache = ReadCache::new(Some(1), None, None).unwrap();
+        cache.insert("/a", b"hello");
+        assert_eq!(
+            cache.get_with("/a", |v| v[1..3].to_vec()),
+            Some(b"el".to_vec())
+        );
+        assert_eq!(cache.get_with("/b", |v| v.len()), None);
+    }
+
+    #[test]
+    fn test_cache_expire_by_ttl() {
+        let cache = ReadCache::new(None, None, Some(Duration::ZERO)).unwrap();
+        cache.insert("/a", b"a");
+        assert_eq!(cache.get("/a"), None);
+    }
+}
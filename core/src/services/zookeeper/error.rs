@@ -0,0 +1,171 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use zookeeper_client as zk;
+
+use crate::Error;
+use crate::ErrorKind;
+
+/// Parse zookeeper error into Error.
+///
+/// Errors are mapped to the closest `ErrorKind`, and transient ones like
+/// connection loss or session expiry are marked as temporary so that they
+/// could be retried. The name of the zookeeper error is attached as `zookeeper_error` context,
+/// and the error code defined by zookeeper server (if any) is attached as
+/// `zookeeper_code` context, both of them show up in the formatted error.
+/// The original `zookeeper_client::Error` is kept as the source, callers can
+/// get it back by downcasting `std::error::Error::source()`.
+pub fn parse_zookeeper_error(e: zk::Error) -> Error {
+    let (name, code) = parse_zookeeper_error_code(&e);
+    let (kind, retryable) = parse_zookeeper_error_kind(&e);
+
+    let mut err = Error::new(kind, "error from zookeeper").with_context("zookeeper_error", name);
+    if let Some(code) = code {
+        err = err.with_context("zookeeper_code", code.to_string());
+    }
+    if retryable {
+        err = err.set_temporary();
+    }
+    err.set_source(e)
+}
+
+/// Parse zookeeper multi write error into Error.
+///
+/// The index of the failed operation is attached as `index` context.
+pub fn parse_multi_write_error(e: zk::MultiWriteError) -> Error {
+    match e {
+        zk::MultiWriteError::RequestFailed { source } => parse_zookeeper_error(source),
+        zk::MultiWriteError::OperationFailed { index, source } => {
+            parse_zookeeper_error(source).with_context("index", index.to_string())
+        }
+    }
+}
+
+/// Returns the `ErrorKind` of the zookeeper error and whether it's
+/// transient.
+fn parse_zookeeper_error_kind(e: &zk::Error) -> (ErrorKind, bool) {
+    match e {
+        zk::Error::NoNode => (ErrorKind::NotFound, false),
+        zk::Error::NodeExists => (ErrorKind::AlreadyExists, false),
+        zk::Error::NoAuth | zk::Error::InvalidAcl | zk::Error::AuthFailed => {
+            (ErrorKind::PermissionDenied, false)
+        }
+        zk::Error::BadVersion => (ErrorKind::ConditionNotMatch, false),
+        zk::Error::BadArguments(_) => (ErrorKind::ConfigInvalid, false),
+        zk::Error::Unimplemented | zk::Error::ReconfigDisabled => (ErrorKind::Unsupported, false),
+        zk::Error::Throttled => (ErrorKind::RateLimited, true),
+        zk::Error::ConnectionLoss
+        | zk::Error::SessionExpired
+        | zk::Error::SessionMoved
+        | zk::Error::Timeout
+        | zk::Error::NoHosts => (ErrorKind::Unexpected, true),
+        _ => (ErrorKind::Unexpected, false),
+    }
+}
+
+/// Returns the name of the zookeeper error and the error code defined by
+/// zookeeper server. Errors raised by the client itself don't have a code.
+fn parse_zookeeper_error_code(e: &zk::Error) -> (&'static str, Option<i32>) {
+    match e {
+        zk::Error::UnmarshalError { .. } => ("UnmarshalError", None),
+        zk::Error::NoHosts => ("NoHosts", None),
+        zk::Error::Timeout => ("Timeout", Some(-7)),
+        zk::Error::UnexpectedError(_) => ("UnexpectedError", None),
+        zk::Error::BadArguments(_) => ("BadArguments", Some(-8)),
+        zk::Error::NoNode => ("NoNode", Some(-101)),
+        zk::Error::NoAuth => ("NoAuth", Some(-102)),
+        zk::Error::BadVersion => ("BadVersion", Some(-103)),
+        zk::Error::NoChildrenForEphemerals => ("NoChildrenForEphemerals", Some(-108)),
+        zk::Error::NodeExists => ("NodeExists", Some(-110)),
+        zk::Error::NotEmpty => ("NotEmpty", Some(-111)),
+        zk::Error::SessionExpired => ("SessionExpired", Some(-112)),
+        zk::Error::InvalidAcl => ("InvalidAcl", Some(-114)),
+        zk::Error::AuthFailed => ("AuthFailed", Some(-115)),
+        zk::Error::SessionMoved => ("SessionMoved", Some(-118)),
+        zk::Error::NotReadOnly => ("NotReadOnly", Some(-119)),
+        zk::Error::NoWatcher => ("NoWatcher", Some(-122)),
+        zk::Error::QuotaExceeded => ("QuotaExceeded", Some(-125)),
+        zk::Error::Throttled => ("Throttled", Some(-127)),
+        zk::Error::MarshallingError => ("MarshallingError", Some(-5)),
+        zk::Error::Unimplemented => ("Unimplemented", Some(-6)),
+        zk::Error::ConnectionLoss => ("ConnectionLoss", Some(-4)),
+        zk::Error::ReconfigDisabled => ("ReconfigDisabled", Some(-123)),
+        zk::Error::UnexpectedErrorCode(code) => ("UnexpectedErrorCode", Some(*code)),
+        zk::Error::ClientClosed => ("ClientClosed", None),
+        _ => ("Unknown", None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_zookeeper_error_code() {
+        let cases = vec![
+            (zk::Error::NoNode, ("NoNode", Some(-101))),
+            (zk::Error::BadVersion, ("BadVersion", Some(-103))),
+            (zk::Error::ConnectionLoss, ("ConnectionLoss", Some(-4))),
+            (
+                zk::Error::UnexpectedErrorCode(-999),
+                ("UnexpectedErrorCode", Some(-999)),
+            ),
+            (zk::Error::ClientClosed, ("ClientClosed", None)),
+        ];
+
+        for (err, expected) in cases {
+            assert_eq!(parse_zookeeper_error_code(&err), expected);
+        }
+    }
+
+    #[test]
+    fn test_parse_zookeeper_error_kind() {
+        let cases = vec![
+            (zk::Error::NoNode, ErrorKind::NotFound, false),
+            (zk::Error::NodeExists, ErrorKind::AlreadyExists, false),
+            (zk::Error::NoAuth, ErrorKind::PermissionDenied, false),
+            (zk::Error::InvalidAcl, ErrorKind::PermissionDenied, false),
+            (
+                zk::Error::BadArguments(&"bad"),
+                ErrorKind::ConfigInvalid,
+                false,
+            ),
+            (zk::Error::ConnectionLoss, ErrorKind::Unexpected, true),
+            (zk::Error::SessionExpired, ErrorKind::Unexpected, true),
+            (zk::Error::NotEmpty, ErrorKind::Unexpected, false),
+        ];
+
+        for (err, kind, temporary) in cases {
+            let err = parse_zookeeper_error(err);
+            assert_eq!(err.kind(), kind, "{err}");
+            assert_eq!(err.is_temporary(), temporary, "{err}");
+        }
+    }
+
+    #[test]
+    fn test_parse_zookeeper_error() {
+        let err = parse_zookeeper_error(zk::Error::NodeExists);
+        let msg = format!("{err}");
+        assert!(msg.contains("zookeeper_error: NodeExists"), "{msg}");
+        assert!(msg.contains("zookeeper_code: -110"), "{msg}");
+
+        let source = std::error::Error::source(&err)
+            .and_then(|e| e.downcast_ref::<zk::Error>())
+            .expect("source must be zookeeper error");
+        assert_eq!(source, &zk::Error::NodeExists);
+    }
+}
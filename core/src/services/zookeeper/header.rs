@@ -0,0 +1,151 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::raw::OpWrite;
+use crate::*;
+
+/// The header of values stored with metadata, followed by the format
+/// version.
+const VALUE_HEADER_MAGIC: &[u8] = b"\0ZKM";
+const VALUE_HEADER_VERSION: u8 = 1;
+
+/// ValueHeader is the metadata stored together with the value when
+/// `store_metadata` is enabled.
+///
+/// Values are stored as `magic | version | header length | header | value`,
+/// the length is 4 bytes big endian and the header is encoded in json.
+/// Values without the magic header are returned as is with an empty header,
+/// so that nodes written before enabling it could still be read.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ValueHeader {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_disposition: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_control: Option<String>,
+}
+
+impl ValueHeader {
+    /// Build the header from the args of write.
+    pub fn from_op(args: &OpWrite) -> Self {
+        Self {
+            content_type: args.content_type().map(|v| v.to_string()),
+            content_disposition: args.content_disposition().map(|v| v.to_string()),
+            cache_control: args.cache_control().map(|v| v.to_string()),
+        }
+    }
+
+    /// Fill the metadata with the fields of header.
+    pub fn apply(&self, meta: &mut Metadata) {
+        if let Some(v) = &self.content_type {
+            meta.set_content_type(v);
+        }
+        if let Some(v) = &self.content_disposition {
+            meta.set_content_disposition(v);
+        }
+        if let Some(v) = &self.cache_control {
+            meta.set_cache_control(v);
+        }
+    }
+
+    /// Prepend the header to value.
+    pub fn encode(&self, value: &[u8]) -> Result<Vec<u8>> {
+        let header = serde_json::to_vec(self).map_err(|e| {
+            Error::new(ErrorKind::Unexpected, "failed to encode value header").set_source(e)
+        })?;
+
+        let mut encoded =
+            Vec::with_capacity(VALUE_HEADER_MAGIC.len() + 5 + header.len() + value.len());
+        encoded.extend_from_slice(VALUE_HEADER_MAGIC);
+        encoded.push(VALUE_HEADER_VERSION);
+        encoded.extend_from_slice(&(header.len() as u32).to_be_bytes());
+        encoded.extend_from_slice(&header);
+        encoded.extend_from_slice(value);
+        Ok(encoded)
+    }
+
+    /// Split the header from value.
+    pub fn decode(value: &[u8]) -> Result<(Self, &[u8])> {
+        let Some(value) = value.strip_prefix(VALUE_HEADER_MAGIC) else {
+            return Ok((Self::default(), value));
+        };
+        let truncated = || Error::new(ErrorKind::Unexpected, "value header is truncated");
+
+        let (version, value) = value.split_first().ok_or_else(truncated)?;
+        if *version != VALUE_HEADER_VERSION {
+            return Err(Error::new(
+                ErrorKind::Unexpected,
+                "value header has unsupported version",
+            )
+            .with_context("version", version.to_string()));
+        }
+        if value.len() < 4 {
+            return Err(truncated());
+        }
+        let (len, value) = value.split_at(4);
+        let len = u32::from_be_bytes([len[0], len[1], len[2], len[3]]) as usize;
+        if value.len() < len {
+            return Err(truncated());
+        }
+
+        let (header, value) = value.split_at(len);
+        let header = serde_json::from_slice(header).map_err(|e| {
+            Error::new(ErrorKind::Unexpected, "failed to decode value header").set_source(e)
+        })?;
+        Ok((header, value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_value_header_roundtrip() {
+        let header = ValueHeader {
+            content_type: Some("application/json".to_string()),
+            ..Default::default()
+        };
+        let encoded = header.encode(b"{}").unwrap();
+        assert!(encoded.starts_with(VALUE_HEADER_MAGIC));
+
+        let (decoded, value) = ValueHeader::decode(&encoded).unwrap();
+        assert_eq!(decoded, header);
+        assert_eq!(value, b"{}");
+    }
+
+    #[test]
+    fn test_value_header_plain_value() {
+        let (header, value) = ValueHeader::decode(b"plain").unwrap();
+        assert_eq!(header, ValueHeader::default());
+        assert_eq!(value, b"plain");
+    }
+
+    #[test]
+    fn test_value_header_invalid() {
+        let mut encoded = ValueHeader::default().encode(b"v").unwrap();
+        assert!(ValueHeader::decode(&encoded[..encoded.len() - 3]).is_err());
+
+        encoded[VALUE_HEADER_MAGIC.len()] = 2;
+        let err = ValueHeader::decode(&encoded).unwrap_err();
+        assert!(format!("{err}").contains("unsupported version"), "{err}");
+    }
+}
@@ -16,5 +16,27 @@
 // under the License.
 
 mod backend;
+mod cache;
+mod error;
+mod header;
+mod proxy;
+mod stats;
+mod trace;
+mod transform;
+mod watch;
 
+pub use backend::FsckReport as ZookeeperFsckReport;
+pub use backend::SubtreeReport as ZookeeperSubtreeReport;
+pub use backend::TreeDiff as ZookeeperTreeDiff;
+pub use backend::ZkAdapter as ZookeeperAdapter;
+pub use backend::ZookeeperBatchOp;
 pub use backend::ZookeeperBuilder as Zookeeper;
+pub use backend::ZookeeperCreateMode;
+pub use backend::ZookeeperDurability;
+pub use backend::ZookeeperSession;
+pub use stats::ZookeeperStats;
+#[cfg(feature = "services-zookeeper-encryption")]
+pub use transform::DecryptError as ZookeeperDecryptError;
+pub use transform::ZookeeperTransformer;
+pub use watch::ZookeeperMapEvent;
+pub use watch::ZookeeperWatchEvent;
@@ -0,0 +1,458 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use std::net::IpAddr;
+use std::sync::Arc;
+
+use log::warn;
+use parking_lot::Mutex;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpListener;
+use tokio::net::TcpStream;
+use tokio::task::JoinHandle;
+
+use super::backend::LOGGING_TARGET;
+use crate::*;
+
+/// The max bytes of the response header of http CONNECT.
+const HTTP_CONNECT_MAX_HEADER: usize = 8 * 1024;
+
+/// The proxy to tunnel connections to zookeeper servers through.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ZookeeperProxy {
+    /// A http proxy supporting the `CONNECT` method, at the address.
+    Http(String),
+    /// A SOCKS5 proxy without authentication, at the address.
+    Socks5(String),
+}
+
+impl ZookeeperProxy {
+    /// Parse the proxy from `http://host:port` or `socks5://host:port`.
+    pub fn parse(proxy: &str) -> Result<Self> {
+        let invalid = |reason: &str| {
+            Error::new(ErrorKind::ConfigInvalid, "invalid zookeeper proxy")
+                .with_context("proxy", proxy)
+                .with_context("reason", reason)
+        };
+
+        let (scheme, addr) = proxy
+            .split_once("://")
+            .ok_or_else(|| invalid("scheme is missing"))?;
+        let addr = addr.strip_suffix('/').unwrap_or(addr);
+        if addr.is_empty() || addr.contains('/') {
+            return Err(invalid("address must be host:port"));
+        }
+        split_host_port(addr).ok_or_else(|| invalid("address must be host:port"))?;
+
+        match scheme {
+            "http" => Ok(Self::Http(addr.to_string())),
+            "socks5" | "socks5h" => Ok(Self::Socks5(addr.to_string())),
+            _ => Err(invalid("scheme must be http or socks5")),
+        }
+    }
+
+    fn addr(&self) -> &str {
+        match self {
+            Self::Http(addr) | Self::Socks5(addr) => addr,
+        }
+    }
+
+    /// Open a tunnel to `target` through the proxy.
+    async fn connect(&self, target: &str) -> Result<TcpStream> {
+        let failed = |reason: String| {
+            Error::new(
+                ErrorKind::Unexpected,
+                "failed to connect to zookeeper through proxy",
+            )
+            .with_context("proxy", self.addr())
+            .with_context("server", target)
+            .with_context("reason", reason)
+            .set_temporary()
+        };
+
+        let mut stream = TcpStream::connect(self.addr())
+            .await
+            .map_err(|e| failed("connect to proxy".to_string()).set_source(e))?;
+        let res = match self {
+            Self::Http(_) => http_connect(&mut stream, target).await,
+            Self::Socks5(_) => socks5_connect(&mut stream, target).await,
+        };
+        match res {
+            Ok(()) => Ok(stream),
+            Err(HandshakeError::Io(e)) => Err(failed("handshake".to_string()).set_source(e)),
+            Err(HandshakeError::Rejected(reason)) => Err(failed(reason)),
+        }
+    }
+}
+
+/// ProxyTunnel listens on one local port for every server of the ensemble,
+/// and forwards all connections accepted on it to the server through the
+/// proxy, so that the zookeeper client can connect to the local ports as
+/// is.
+///
+/// The listeners are stopped once the tunnel is dropped.
+pub struct ProxyTunnel {
+    endpoint: String,
+    last_error: Arc<Mutex<Option<Error>>>,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl ProxyTunnel {
+    /// Start listening for the servers of connect string `endpoint`, log
+    /// messages are prefixed with `log_prefix`.
+    pub async fn start(proxy: &ZookeeperProxy, endpoint: &str, log_prefix: &str) -> Result<Self> {
+        let (hosts, chroot) = match endpoint.find('/') {
+            Some(idx) => endpoint.split_at(idx),
+            None => (endpoint, ""),
+        };
+
+        let mut tunnel = Self {
+            endpoint: String::new(),
+            last_error: Arc::default(),
+            tasks: Vec::new(),
+        };
+        let mut locals = Vec::new();
+        for server in hosts.split(',') {
+            let listener = TcpListener::bind("127.0.0.1:0").await.map_err(|e| {
+                Error::new(
+                    ErrorKind::Unexpected,
+                    "failed to listen for zookeeper proxy",
+                )
+                .set_source(e)
+            })?;
+            locals.push(
+                listener
+                    .local_addr()
+                    .map_err(|e| {
+                        Error::new(
+                            ErrorKind::Unexpected,
+                            "failed to listen for zookeeper proxy",
+                        )
+                        .set_source(e)
+                    })?
+                    .to_string(),
+            );
+            tunnel.tasks.push(tokio::spawn(forward(
+                listener,
+                proxy.clone(),
+                server.to_string(),
+                tunnel.last_error.clone(),
+                Arc::from(log_prefix),
+            )));
+        }
+        tunnel.endpoint = format!("{}{chroot}", locals.join(","));
+        Ok(tunnel)
+    }
+
+    /// The connect string of the local ports.
+    pub fn endpoint(&self) -> &str {
+        &self.endpoint
+    }
+
+    /// Take the last error of tunneling through the proxy.
+    pub fn take_error(&self) -> Option<Error> {
+        self.last_error.lock().take()
+    }
+}
+
+impl Drop for ProxyTunnel {
+    fn drop(&mut self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
+
+/// Accept local connections and forward them to `server` through `proxy`.
+async fn forward(
+    listener: TcpListener,
+    proxy: ZookeeperProxy,
+    server: String,
+    last_error: Arc<Mutex<Option<Error>>>,
+    log_prefix: Arc<str>,
+) {
+    while let Ok((mut local, _)) = listener.accept().await {
+        let proxy = proxy.clone();
+        let server = server.clone();
+        let last_error = last_error.clone();
+        let log_prefix = log_prefix.clone();
+        tokio::spawn(async move {
+            match proxy.connect(&server).await {
+                Ok(mut remote) => {
+                    let _ = tokio::io::copy_bidirectional(&mut local, &mut remote).await;
+                }
+                // The client sees the connection closed and tries again,
+                // keep the error so that it could be returned on timeout.
+                Err(e) => {
+                    warn!(target: LOGGING_TARGET, "{log_prefix}proxy forward failed: {e}");
+                    *last_error.lock() = Some(e);
+                }
+            }
+        });
+    }
+}
+
+enum HandshakeError {
+    Io(std::io::Error),
+    Rejected(String),
+}
+
+impl From<std::io::Error> for HandshakeError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+/// Open the tunnel by http `CONNECT`.
+async fn http_connect(
+    stream: &mut TcpStream,
+    target: &str,
+) -> std::result::Result<(), HandshakeError> {
+    stream
+        .write_all(format!("CONNECT {target} HTTP/1.1\r\nHost: {target}\r\n\r\n").as_bytes())
+        .await?;
+
+    // Read byte by byte to not consume anything after the header.
+    let mut header = Vec::new();
+    while !header.ends_with(b"\r\n\r\n") {
+        if header.len() >= HTTP_CONNECT_MAX_HEADER {
+            return Err(HandshakeError::Rejected(
+                "http CONNECT response header is too large".to_string(),
+            ));
+        }
+        header.push(stream.read_u8().await?);
+    }
+
+    let header = String::from_utf8_lossy(&header);
+    let status = header.lines().next().unwrap_or_default();
+    match status.split_whitespace().nth(1) {
+        Some(code) if code.starts_with('2') => Ok(()),
+        _ => Err(HandshakeError::Rejected(format!(
+            "http CONNECT responded {status}"
+        ))),
+    }
+}
+
+/// Open the tunnel by SOCKS5 `CONNECT` without authentication.
+async fn socks5_connect(
+    stream: &mut TcpStream,
+    target: &str,
+) -> std::result::Result<(), HandshakeError> {
+    let (host, port) = split_host_port(target)
+        .ok_or_else(|| HandshakeError::Rejected("server must be host:port".to_string()))?;
+
+    stream.write_all(&[5, 1, 0]).await?;
+    let mut reply = [0; 2];
+    stream.read_exact(&mut reply).await?;
+    if reply != [5, 0] {
+        return Err(HandshakeError::Rejected(
+            "socks5 proxy requires authentication".to_string(),
+        ));
+    }
+
+    let mut request = vec![5, 1, 0];
+    match host.parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) => {
+            request.push(1);
+            request.extend_from_slice(&ip.octets());
+        }
+        Ok(IpAddr::V6(ip)) => {
+            request.push(4);
+            request.extend_from_slice(&ip.octets());
+        }
+        // Leave hostnames to be resolved by the proxy.
+        Err(_) => {
+            let len = u8::try_from(host.len())
+                .map_err(|_| HandshakeError::Rejected("hostname is too long".to_string()))?;
+            request.push(3);
+            request.push(len);
+            request.extend_from_slice(host.as_bytes());
+        }
+    }
+    request.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&request).await?;
+
+    let mut reply = [0; 4];
+    stream.read_exact(&mut reply).await?;
+    if reply[1] != 0 {
+        return Err(HandshakeError::Rejected(format!(
+            "socks5 proxy replied {}",
+            reply[1]
+        )));
+    }
+    // Skip the bound address.
+    let len = match reply[3] {
+        1 => 4,
+        4 => 16,
+        3 => stream.read_u8().await? as usize,
+        atyp => {
+            return Err(HandshakeError::Rejected(format!(
+                "socks5 proxy replied unknown address type {atyp}"
+            )))
+        }
+    };
+    let mut addr = vec![0; len + 2];
+    stream.read_exact(&mut addr).await?;
+    Ok(())
+}
+
+/// Split `host:port`, brackets around ipv6 hosts are removed.
+fn split_host_port(addr: &str) -> Option<(&str, u16)> {
+    let (host, port) = addr.rsplit_once(':')?;
+    let host = host
+        .strip_prefix('[')
+        .and_then(|host| host.strip_suffix(']'))
+        .unwrap_or(host);
+    if host.is_empty() {
+        return None;
+    }
+    Some((host, port.parse().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_proxy() {
+        assert_eq!(
+            ZookeeperProxy::parse("http://proxy:3128").unwrap(),
+            ZookeeperProxy::Http("proxy:3128".to_string())
+        );
+        assert_eq!(
+            ZookeeperProxy::parse("socks5://127.0.0.1:1080/").unwrap(),
+            ZookeeperProxy::Socks5("127.0.0.1:1080".to_string())
+        );
+
+        for proxy in [
+            "proxy:3128",
+            "ftp://proxy:21",
+            "http://proxy",
+            "http://proxy:1/a",
+        ] {
+            let err = ZookeeperProxy::parse(proxy).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::ConfigInvalid, "{proxy}");
+        }
+    }
+
+    #[test]
+    fn test_split_host_port() {
+        assert_eq!(split_host_port("zk1:2181"), Some(("zk1", 2181)));
+        assert_eq!(split_host_port("[::1]:2181"), Some(("::1", 2181)));
+        assert_eq!(split_host_port("zk1"), None);
+        assert_eq!(split_host_port(":2181"), None);
+    }
+
+    /// Start a fake http proxy that replies the CONNECT with `status`, and
+    /// echoes everything after it. Returns the address of the proxy and the
+    /// receiver of the requested target.
+    async fn fake_http_proxy(
+        status: &'static str,
+    ) -> (String, tokio::sync::oneshot::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut header = Vec::new();
+            while !header.ends_with(b"\r\n\r\n") {
+                header.push(stream.read_u8().await.unwrap());
+            }
+            let _ = tx.send(String::from_utf8(header).unwrap());
+            stream
+                .write_all(format!("HTTP/1.1 {status}\r\n\r\n").as_bytes())
+                .await
+                .unwrap();
+            let (mut r, mut w) = stream.split();
+            let _ = tokio::io::copy(&mut r, &mut w).await;
+        });
+        (addr, rx)
+    }
+
+    #[tokio::test]
+    async fn test_tunnel_http_connect() {
+        let (addr, target) = fake_http_proxy("200 Connection established").await;
+        let tunnel = ProxyTunnel::start(&ZookeeperProxy::Http(addr), "zk1:2181/app", "")
+            .await
+            .unwrap();
+        assert!(tunnel.endpoint().ends_with("/app"));
+
+        let local = tunnel.endpoint().strip_suffix("/app").unwrap();
+        let mut stream = TcpStream::connect(local).await.unwrap();
+        stream.write_all(b"ping").await.unwrap();
+        let mut buf = [0; 4];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+        assert!(target
+            .await
+            .unwrap()
+            .starts_with("CONNECT zk1:2181 HTTP/1.1\r\n"));
+        assert!(tunnel.take_error().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_tunnel_http_connect_rejected() {
+        let (addr, _target) = fake_http_proxy("403 Forbidden").await;
+        let tunnel = ProxyTunnel::start(&ZookeeperProxy::Http(addr), "zk1:2181", "")
+            .await
+            .unwrap();
+
+        let mut stream = TcpStream::connect(tunnel.endpoint()).await.unwrap();
+        let mut buf = Vec::new();
+        // The local connection is closed once the handshake failed.
+        stream.read_to_end(&mut buf).await.unwrap();
+        let err = tunnel.take_error().expect("handshake must fail");
+        assert_eq!(err.kind(), ErrorKind::Unexpected);
+        assert!(err.is_temporary());
+        assert!(format!("{err}").contains("403 Forbidden"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn test_tunnel_socks5() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let proxy = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut greeting = [0; 3];
+            stream.read_exact(&mut greeting).await.unwrap();
+            stream.write_all(&[5, 0]).await.unwrap();
+            let mut request = [0; 5];
+            stream.read_exact(&mut request).await.unwrap();
+            let mut host = vec![0; request[4] as usize + 2];
+            stream.read_exact(&mut host).await.unwrap();
+            stream
+                .write_all(&[5, 0, 0, 1, 127, 0, 0, 1, 0, 0])
+                .await
+                .unwrap();
+            stream.write_all(b"pong").await.unwrap();
+            (request, host)
+        });
+
+        let tunnel = ProxyTunnel::start(&ZookeeperProxy::Socks5(addr), "zk1:2181", "")
+            .await
+            .unwrap();
+        let mut stream = TcpStream::connect(tunnel.endpoint()).await.unwrap();
+        let mut buf = [0; 4];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"pong");
+
+        let (request, host) = proxy.await.unwrap();
+        assert_eq!(request, [5, 1, 0, 3, 3]);
+        assert_eq!(host, b"zk1\x08\x85");
+    }
+}
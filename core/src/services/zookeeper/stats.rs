@@ -0,0 +1,171 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use std::collections::BTreeMap;
+
+use chrono::DateTime;
+use chrono::Utc;
+use parking_lot::Mutex;
+
+use crate::*;
+
+/// Stats collects the counters of zookeeper operations.
+#[derive(Debug, Default)]
+pub struct Stats {
+    inner: Mutex<ZookeeperStats>,
+}
+
+impl Stats {
+    /// Record the result of given operation.
+    pub fn record<T>(&self, op: &'static str, res: &Result<T>) {
+        let mut inner = self.inner.lock();
+        *inner.ops.entry(op.to_string()).or_default() += 1;
+        match res {
+            Ok(_) => inner.last_success = Some(Utc::now()),
+            Err(e) => *inner.errors.entry(e.kind().to_string()).or_default() += 1,
+        }
+    }
+
+    /// Take a snapshot of current counters.
+    pub fn snapshot(&self) -> ZookeeperStats {
+        self.inner.lock().clone()
+    }
+}
+
+/// The snapshot of counters of a zookeeper backend.
+///
+/// Counters are shared by all clones of the backend and never reset.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ZookeeperStats {
+    /// The number of operations by type, including `connect` which counts
+    /// every (re)connection attempt.
+    pub ops: BTreeMap<String, u64>,
+    /// The number of failed operations by error kind.
+    pub errors: BTreeMap<String, u64>,
+    /// The time of the last successful operation.
+    pub last_success: Option<DateTime<Utc>>,
+}
+
+impl ZookeeperStats {
+    /// Render the counters in prometheus text exposition format, with
+    /// `labels` attached to every sample.
+    #[cfg(feature = "services-zookeeper-prometheus")]
+    pub fn to_prometheus_text(&self, labels: &[(&str, &str)]) -> String {
+        use std::fmt::Write;
+
+        let render = |extra: Option<(&str, &str)>| {
+            let pairs: Vec<String> = labels
+                .iter()
+                .copied()
+                .chain(extra)
+                .map(|(k, v)| format!("{k}=\"{}\"", escape_label_value(v)))
+                .collect();
+            format!("{{{}}}", pairs.join(","))
+        };
+
+        let mut s = String::new();
+        let _ = writeln!(
+            s,
+            "# HELP opendal_zookeeper_operations_total The number of zookeeper operations by type."
+        );
+        let _ = writeln!(s, "# TYPE opendal_zookeeper_operations_total counter");
+        for (op, count) in &self.ops {
+            let labels = render(Some(("op", op)));
+            let _ = writeln!(s, "opendal_zookeeper_operations_total{labels} {count}");
+        }
+
+        let _ = writeln!(
+            s,
+            "# HELP opendal_zookeeper_errors_total The number of failed zookeeper operations by error kind."
+        );
+        let _ = writeln!(s, "# TYPE opendal_zookeeper_errors_total counter");
+        for (kind, count) in &self.errors {
+            let labels = render(Some(("kind", kind)));
+            let _ = writeln!(s, "opendal_zookeeper_errors_total{labels} {count}");
+        }
+
+        let _ = writeln!(
+            s,
+            "# HELP opendal_zookeeper_last_success_timestamp_seconds The unix time of the last successful zookeeper operation."
+        );
+        let _ = writeln!(
+            s,
+            "# TYPE opendal_zookeeper_last_success_timestamp_seconds gauge"
+        );
+        if let Some(last_success) = self.last_success {
+            let labels = render(None);
+            let ts = last_success.timestamp_millis() as f64 / 1000.0;
+            let _ = writeln!(
+                s,
+                "opendal_zookeeper_last_success_timestamp_seconds{labels} {ts}"
+            );
+        }
+        s
+    }
+}
+
+/// Escape the label value as required by prometheus text format.
+#[cfg(feature = "services-zookeeper-prometheus")]
+fn escape_label_value(v: &str) -> String {
+    v.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stats_record() {
+        let stats = Stats::default();
+        stats.record("get", &Ok(()));
+        stats.record(
+            "get",
+            &Result::<()>::Err(Error::new(ErrorKind::NotFound, "")),
+        );
+        stats.record("set", &Ok(()));
+
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.ops.get("get"), Some(&2));
+        assert_eq!(snapshot.ops.get("set"), Some(&1));
+        assert_eq!(snapshot.errors.get("NotFound"), Some(&1));
+        assert!(snapshot.last_success.is_some());
+    }
+
+    #[cfg(feature = "services-zookeeper-prometheus")]
+    #[test]
+    fn test_to_prometheus_text() {
+        let stats = Stats::default();
+        stats.record("get", &Ok(()));
+        stats.record(
+            "set",
+            &Result::<()>::Err(Error::new(ErrorKind::Unexpected, "")),
+        );
+
+        let text = stats
+            .snapshot()
+            .to_prometheus_text(&[("endpoint", "127.0.0.1:2181"), ("label", "a\"b")]);
+        assert!(text.contains(
+            "opendal_zookeeper_operations_total{endpoint=\"127.0.0.1:2181\",label=\"a\\\"b\",op=\"get\"} 1"
+        ));
+        assert!(text.contains(
+            "opendal_zookeeper_errors_total{endpoint=\"127.0.0.1:2181\",label=\"a\\\"b\",kind=\"Unexpected\"} 1"
+        ));
+        assert!(text.contains("opendal_zookeeper_last_success_timestamp_seconds{"));
+    }
+}
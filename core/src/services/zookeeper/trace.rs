@@ -0,0 +1,75 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use std::future::Future;
+
+use crate::*;
+
+/// Run the future of given zookeeper operation within an opentelemetry span.
+///
+/// The span carries `scheme`, `op`, `path` and the result `status` as
+/// attributes. Credentials and values must never be passed in. Attributes
+/// are only built if the span is recording, so this is almost free when no
+/// tracer provider is installed.
+#[cfg(feature = "layers-otel-trace")]
+pub async fn in_span<T>(
+    op: &'static str,
+    path: &str,
+    fut: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    use opentelemetry::global;
+    use opentelemetry::trace::FutureExt;
+    use opentelemetry::trace::Span;
+    use opentelemetry::trace::Status;
+    use opentelemetry::trace::TraceContextExt;
+    use opentelemetry::trace::Tracer;
+    use opentelemetry::Context as TraceContext;
+    use opentelemetry::KeyValue;
+
+    let tracer = global::tracer("opendal");
+    let mut span = tracer.start(op);
+    if !span.is_recording() {
+        return fut.await;
+    }
+    span.set_attribute(KeyValue::new("scheme", Scheme::Zookeeper.into_static()));
+    span.set_attribute(KeyValue::new("op", op));
+    span.set_attribute(KeyValue::new("path", path.to_string()));
+
+    let cx = TraceContext::current_with_span(span);
+    let res = fut.with_context(cx.clone()).await;
+
+    let span = cx.span();
+    match &res {
+        Ok(_) => span.set_attribute(KeyValue::new("status", "ok")),
+        Err(e) => {
+            span.set_attribute(KeyValue::new("status", e.kind().into_static()));
+            span.set_status(Status::error(e.to_string()));
+        }
+    }
+    res
+}
+
+/// Run the future of given zookeeper operation directly since tracing is
+/// not enabled.
+#[cfg(not(feature = "layers-otel-trace"))]
+pub async fn in_span<T>(
+    _: &'static str,
+    _: &str,
+    fut: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    fut.await
+}
@@ -0,0 +1,245 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use std::fmt::Debug;
+
+use crate::*;
+
+/// ZookeeperTransformer transforms values before they are written to and
+/// after they are read from zookeeper, for example to compress or encrypt
+/// them client side.
+///
+/// `decode` must be the inverse of `encode`. Implementations are encouraged
+/// to tag encoded values with a format header and pass untagged values
+/// through `decode` as is, so that transformed and plain nodes can coexist
+/// while migrating. Transformers like encryption should reject untagged
+/// values instead, unless asked to accept them.
+pub trait ZookeeperTransformer: Debug + Send + Sync + 'static {
+    /// Encode the value to be written.
+    fn encode(&self, value: &[u8]) -> Result<Vec<u8>>;
+
+    /// Decode the value that has been read.
+    fn decode(&self, value: &[u8]) -> Result<Vec<u8>>;
+}
+
+/// The header of values encrypted by [`AesGcmTransformer`], followed by
+/// the format version.
+#[cfg(feature = "services-zookeeper-encryption")]
+const AES_GCM_MAGIC: &[u8] = b"\0ZKE";
+#[cfg(feature = "services-zookeeper-encryption")]
+const AES_GCM_VERSION: u8 = 1;
+
+/// AesGcmTransformer encrypts values with AES-256-GCM.
+///
+/// Encrypted values are stored as `magic | version | nonce | ciphertext | tag`
+/// with a random nonce for every value. Values without the magic header are
+/// rejected, unless [`AesGcmTransformer::allow_plaintext`] is set.
+///
+/// Decryption errors are returned as `ErrorKind::Unexpected` with
+/// `reason: decrypt failed` in context and a [`DecryptError`] source.
+#[cfg(feature = "services-zookeeper-encryption")]
+pub struct AesGcmTransformer {
+    key: ring::aead::LessSafeKey,
+    rng: ring::rand::SystemRandom,
+    allow_plaintext: bool,
+}
+
+#[cfg(feature = "services-zookeeper-encryption")]
+impl AesGcmTransformer {
+    /// Create a new transformer with a 32 bytes key.
+    pub fn new(key: &[u8]) -> Result<Self> {
+        let key = ring::aead::UnboundKey::new(&ring::aead::AES_256_GCM, key).map_err(|_| {
+            Error::new(
+                ErrorKind::ConfigInvalid,
+                "encryption key must be 32 bytes for AES-256-GCM",
+            )
+        })?;
+
+        Ok(Self {
+            key: ring::aead::LessSafeKey::new(key),
+            rng: ring::rand::SystemRandom::new(),
+            allow_plaintext: false,
+        })
+    }
+
+    /// Return values without the magic header as is instead of rejecting
+    /// them.
+    ///
+    /// This is meant for migrating existing plaintext nodes only: anyone
+    /// who can write the nodes could inject plaintext values that are
+    /// accepted silently.
+    pub fn allow_plaintext(mut self) -> Self {
+        self.allow_plaintext = true;
+        self
+    }
+}
+
+/// DecryptError is the source of errors returned for values that can't be
+/// decrypted, exported as `ZookeeperDecryptError`.
+///
+/// The error kind is `ErrorKind::Unexpected`, callers can tell decrypt
+/// failures apart by downcasting the source:
+///
+/// ```
+/// use opendal::services::ZookeeperDecryptError;
+///
+/// fn is_decrypt_failed(err: &opendal::Error) -> bool {
+///     std::error::Error::source(err)
+///         .and_then(|e| e.downcast_ref::<ZookeeperDecryptError>())
+///         .is_some()
+/// }
+/// ```
+#[cfg(feature = "services-zookeeper-encryption")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecryptError(&'static str);
+
+#[cfg(feature = "services-zookeeper-encryption")]
+impl std::fmt::Display for DecryptError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+#[cfg(feature = "services-zookeeper-encryption")]
+impl std::error::Error for DecryptError {}
+
+/// Build the error for values that can't be decrypted.
+#[cfg(feature = "services-zookeeper-encryption")]
+fn decrypt_error(message: &'static str) -> Error {
+    Error::new(ErrorKind::Unexpected, message)
+        .with_context("reason", "decrypt failed")
+        .set_source(DecryptError(message))
+}
+
+#[cfg(feature = "services-zookeeper-encryption")]
+impl Debug for AesGcmTransformer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AesGcmTransformer").finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "services-zookeeper-encryption")]
+impl ZookeeperTransformer for AesGcmTransformer {
+    fn encode(&self, value: &[u8]) -> Result<Vec<u8>> {
+        use ring::aead::Aad;
+        use ring::aead::Nonce;
+        use ring::aead::NONCE_LEN;
+        use ring::rand::SecureRandom;
+
+        let mut nonce = [0; NONCE_LEN];
+        self.rng.fill(&mut nonce).map_err(|_| {
+            Error::new(
+                ErrorKind::Unexpected,
+                "failed to generate nonce for encryption",
+            )
+        })?;
+
+        let mut data = value.to_vec();
+        self.key
+            .seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::empty(), &mut data)
+            .map_err(|_| Error::new(ErrorKind::Unexpected, "failed to encrypt value"))?;
+
+        let mut encoded = Vec::with_capacity(AES_GCM_MAGIC.len() + 1 + NONCE_LEN + data.len());
+        encoded.extend_from_slice(AES_GCM_MAGIC);
+        encoded.push(AES_GCM_VERSION);
+        encoded.extend_from_slice(&nonce);
+        encoded.extend_from_slice(&data);
+        Ok(encoded)
+    }
+
+    fn decode(&self, value: &[u8]) -> Result<Vec<u8>> {
+        use ring::aead::Aad;
+        use ring::aead::Nonce;
+        use ring::aead::NONCE_LEN;
+
+        let Some(value) = value.strip_prefix(AES_GCM_MAGIC) else {
+            if self.allow_plaintext {
+                return Ok(value.to_vec());
+            }
+            return Err(decrypt_error("value is not encrypted"));
+        };
+        let (version, value) = value
+            .split_first()
+            .ok_or_else(|| decrypt_error("encrypted value is truncated"))?;
+        if *version != AES_GCM_VERSION {
+            return Err(decrypt_error("encrypted value has unsupported version")
+                .with_context("version", version.to_string()));
+        }
+        if value.len() < NONCE_LEN {
+            return Err(decrypt_error("encrypted value is truncated"));
+        }
+
+        let (nonce, data) = value.split_at(NONCE_LEN);
+        let nonce =
+            Nonce::try_assume_unique_for_key(nonce).map_err(|_| decrypt_error("invalid nonce"))?;
+        let mut data = data.to_vec();
+        let len = self
+            .key
+            .open_in_place(nonce, Aad::empty(), &mut data)
+            .map_err(|_| decrypt_error("failed to decrypt value"))?
+            .len();
+        data.truncate(len);
+        Ok(data)
+    }
+}
+
+#[cfg(all(test, feature = "services-zookeeper-encryption"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_aes_gcm_roundtrip() {
+        let transformer = AesGcmTransformer::new(&[7; 32]).unwrap();
+        let encoded = transformer.encode(b"hello").unwrap();
+        assert!(encoded.starts_with(AES_GCM_MAGIC));
+        assert_ne!(&encoded[AES_GCM_MAGIC.len()..], b"hello");
+        assert_eq!(transformer.decode(&encoded).unwrap(), b"hello");
+    }
+
+    #[test]
+    fn test_aes_gcm_plaintext_passthrough() {
+        let transformer = AesGcmTransformer::new(&[7; 32]).unwrap();
+        let err = transformer.decode(b"hello").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Unexpected);
+        assert!(format!("{err}").contains("not encrypted"), "{err}");
+
+        let transformer = transformer.allow_plaintext();
+        assert_eq!(transformer.decode(b"hello").unwrap(), b"hello");
+    }
+
+    #[test]
+    fn test_aes_gcm_wrong_key() {
+        let encoded = AesGcmTransformer::new(&[7; 32])
+            .unwrap()
+            .encode(b"hello")
+            .unwrap();
+        let err = AesGcmTransformer::new(&[8; 32])
+            .unwrap()
+            .decode(&encoded)
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Unexpected);
+        assert!(format!("{err}").contains("reason: decrypt failed"), "{err}");
+        let source = std::error::Error::source(&err).and_then(|e| e.downcast_ref::<DecryptError>());
+        assert_eq!(source, Some(&DecryptError("failed to decrypt value")));
+    }
+
+    #[test]
+    fn test_aes_gcm_invalid_key() {
+        let err = AesGcmTransformer::new(&[7; 16]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ConfigInvalid);
+    }
+}
@@ -0,0 +1,510 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+
+use futures::future::BoxFuture;
+use futures::future::Either;
+use futures::stream::BoxStream;
+use futures::stream::FuturesUnordered;
+use futures::FutureExt;
+use futures::StreamExt;
+use zookeeper_client as zk;
+
+use super::backend::join_zookeeper_path;
+use super::backend::ZkAdapter;
+use super::error::parse_zookeeper_error;
+use crate::*;
+
+/// The change of the map watched by [`ZkAdapter::watch_map`].
+///
+/// Keys are the names of the children of the watched prefix.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ZookeeperMapEvent {
+    /// A child has been created.
+    Added {
+        /// The name of the child.
+        key: String,
+        /// The value of the child.
+        value: Vec<u8>,
+    },
+    /// The value of a child has been changed.
+    Updated {
+        /// The name of the child.
+        key: String,
+        /// The new value of the child.
+        value: Vec<u8>,
+    },
+    /// A child has been removed.
+    Removed {
+        /// The name of the child.
+        key: String,
+    },
+    /// The session has been lost and the map has been fetched again, all
+    /// previous state should be replaced by the given map.
+    Reset(HashMap<String, Vec<u8>>),
+}
+
+/// The change of the node watched by [`ZkAdapter::watch`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ZookeeperWatchEvent {
+    /// The node has been created.
+    Created {
+        /// The value of the node.
+        value: Vec<u8>,
+    },
+    /// The value of the node has been changed.
+    Changed {
+        /// The new value of the node.
+        value: Vec<u8>,
+    },
+    /// The node has been deleted.
+    Deleted,
+    /// The children of the node have been changed.
+    ChildrenChanged {
+        /// The names of the current children.
+        children: Vec<String>,
+    },
+}
+
+/// KeyWatcher follows one node by a data watch on it, and a children watch
+/// while it exists.
+pub struct KeyWatcher {
+    adapter: ZkAdapter,
+    path: String,
+    client: zk::Client,
+
+    /// The version of the node, `None` if it doesn't exist.
+    version: Option<i32>,
+    data_watch: BoxFuture<'static, zk::WatchedEvent>,
+    children_watch: BoxFuture<'static, zk::WatchedEvent>,
+    done: bool,
+}
+
+impl KeyWatcher {
+    /// Create a new watcher on the current state of the node.
+    pub async fn new(adapter: ZkAdapter, path: String) -> Result<Self> {
+        let client = adapter.get_connection().await?;
+        let mut watcher = Self {
+            adapter,
+            path,
+            client,
+            version: None,
+            data_watch: futures::future::pending().boxed(),
+            children_watch: futures::future::pending().boxed(),
+            done: false,
+        };
+        watcher.refresh().await?;
+        if watcher.version.is_some() {
+            watcher.watch_children().await?;
+        }
+        Ok(watcher)
+    }
+
+    /// Convert into the stream of changes, the stream ends after the first
+    /// error.
+    pub fn into_stream(self) -> BoxStream<'static, Result<ZookeeperWatchEvent>> {
+        futures::stream::unfold(self, |mut watcher| async move {
+            let event = watcher.next_event().await?;
+            Some((event, watcher))
+        })
+        .boxed()
+    }
+
+    async fn next_event(&mut self) -> Option<Result<ZookeeperWatchEvent>> {
+        loop {
+            if self.done {
+                return None;
+            }
+
+            let (event, is_data) =
+                match futures::future::select(&mut self.data_watch, &mut self.children_watch).await
+                {
+                    Either::Left((event, _)) => (event, true),
+                    Either::Right((event, _)) => (event, false),
+                };
+            let res = if event.event_type == zk::EventType::Session {
+                self.reset().await
+            } else if is_data {
+                self.on_data_changed().await
+            } else {
+                self.on_children_changed().await
+            };
+            match res {
+                Ok(Some(event)) => return Some(Ok(event)),
+                Ok(None) => continue,
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+
+    async fn on_data_changed(&mut self) -> Result<Option<ZookeeperWatchEvent>> {
+        let existed = self.version.is_some();
+        let event = self.refresh().await?;
+        match (existed, self.version.is_some()) {
+            (false, true) => {
+                self.watch_children().await?;
+            }
+            (true, false) => self.children_watch = futures::future::pending().boxed(),
+            _ => {}
+        }
+        Ok(event)
+    }
+
+    async fn on_children_changed(&mut self) -> Result<Option<ZookeeperWatchEvent>> {
+        if self.version.is_none() {
+            return Ok(None);
+        }
+        // A deleted node is reported by the data watch.
+        Ok(self
+            .watch_children()
+            .await?
+            .map(|children| ZookeeperWatchEvent::ChildrenChanged { children }))
+    }
+
+    /// Watch the node again on a new session after session lost.
+    async fn reset(&mut self) -> Result<Option<ZookeeperWatchEvent>> {
+        self.client = self.adapter.get_connection().await?;
+        let event = self.refresh().await?;
+        if self.version.is_some() {
+            self.watch_children().await?;
+        } else {
+            self.children_watch = futures::future::pending().boxed();
+        }
+        Ok(event)
+    }
+
+    /// Read the node and watch for changes of it, returns the change since
+    /// last read.
+    async fn refresh(&mut self) -> Result<Option<ZookeeperWatchEvent>> {
+        let current = loop {
+            match self.client.get_and_watch_data(&self.path).await {
+                Ok((data, stat, watcher)) => {
+                    self.data_watch = watcher.changed().boxed();
+                    break Some((self.adapter.decode_value(data)?, stat.version));
+                }
+                Err(zk::Error::NoNode) => {}
+                Err(e) => return Err(parse_zookeeper_error(e)),
+            }
+
+            let (stat, watcher) = self
+                .client
+                .check_and_watch_stat(&self.path)
+                .await
+                .map_err(parse_zookeeper_error)?;
+            if stat.is_none() {
+                // Still missing, wait for it to be created.
+                self.data_watch = watcher.changed().boxed();
+                break None;
+            }
+            // Node has been created in between, read it again.
+        };
+
+        let previous = self.version;
+        self.version = current.as_ref().map(|(_, version)| *version);
+        Ok(watch_event(previous, current))
+    }
+
+    /// List the children of node and watch for changes of them, returns
+    /// `None` if the node doesn't exist.
+    async fn watch_children(&mut self) -> Result<Option<Vec<String>>> {
+        match self.client.get_and_watch_children(&self.path).await {
+            Ok((children, _, watcher)) => {
+                self.children_watch = watcher.changed().boxed();
+                Ok(Some(children))
+            }
+            Err(zk::Error::NoNode) => {
+                self.children_watch = futures::future::pending().boxed();
+                Ok(None)
+            }
+            Err(e) => Err(parse_zookeeper_error(e)),
+        }
+    }
+}
+
+/// Returns the event of node changed from `previous` version to `current`
+/// value and version, `None` if nothing has changed.
+fn watch_event(
+    previous: Option<i32>,
+    current: Option<(Vec<u8>, i32)>,
+) -> Option<ZookeeperWatchEvent> {
+    match (previous, current) {
+        (None, Some((value, _))) => Some(ZookeeperWatchEvent::Created { value }),
+        (Some(_), None) => Some(ZookeeperWatchEvent::Deleted),
+        (Some(previous), Some((value, version))) if previous != version => {
+            Some(ZookeeperWatchEvent::Changed { value })
+        }
+        _ => None,
+    }
+}
+
+/// MapWatcher maintains the children of prefix with their values by one
+/// children watch on the prefix and one data watch on every child.
+pub struct MapWatcher {
+    adapter: ZkAdapter,
+    prefix: String,
+    client: zk::Client,
+
+    map: HashMap<String, Vec<u8>>,
+    children_watch: BoxFuture<'static, zk::WatchedEvent>,
+    data_watches: FuturesUnordered<BoxFuture<'static, (String, zk::WatchedEvent)>>,
+    /// The children with a pending data watch.
+    watched: HashSet<String>,
+    pending: VecDeque<ZookeeperMapEvent>,
+    done: bool,
+}
+
+/// The watch that fired.
+enum Fired {
+    Children(zk::WatchedEvent),
+    Data(String, zk::WatchedEvent),
+}
+
+impl MapWatcher {
+    /// Create a new watcher and fetch the initial map.
+    pub async fn new(adapter: ZkAdapter, prefix: String) -> Result<Self> {
+        let client = adapter.get_connection().await?;
+        let mut watcher = Self {
+            adapter,
+            prefix,
+            client,
+            map: HashMap::new(),
+            children_watch: futures::future::pending().boxed(),
+            data_watches: FuturesUnordered::new(),
+            watched: HashSet::new(),
+            pending: VecDeque::new(),
+            done: false,
+        };
+        watcher.resync().await?;
+        Ok(watcher)
+    }
+
+    /// Get the current map.
+    pub fn snapshot(&self) -> HashMap<String, Vec<u8>> {
+        self.map.clone()
+    }
+
+    /// Convert into the stream of changes, the stream ends after the first
+    /// error.
+    pub fn into_stream(self) -> BoxStream<'static, Result<ZookeeperMapEvent>> {
+        futures::stream::unfold(self, |mut watcher| async move {
+            let event = watcher.next_event().await?;
+            Some((event, watcher))
+        })
+        .boxed()
+    }
+
+    async fn next_event(&mut self) -> Option<Result<ZookeeperMapEvent>> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Some(Ok(event));
+            }
+            if self.done {
+                return None;
+            }
+
+            let fired = if self.data_watches.is_empty() {
+                Fired::Children((&mut self.children_watch).await)
+            } else {
+                match futures::future::select(&mut self.children_watch, self.data_watches.next())
+                    .await
+                {
+                    Either::Left((event, _)) => Fired::Children(event),
+                    Either::Right((Some((key, event)), _)) => Fired::Data(key, event),
+                    Either::Right((None, _)) => continue,
+                }
+            };
+            let res = match fired {
+                Fired::Children(event) => self.on_children_changed(event).await,
+                Fired::Data(key, event) => self.on_data_changed(key, event).await,
+            };
+            if let Err(e) = res {
+                self.done = true;
+                return Some(Err(e));
+            }
+        }
+    }
+
+    async fn on_children_changed(&mut self, event: zk::WatchedEvent) -> Result<()> {
+        if event.event_type == zk::EventType::Session {
+            return self.reset().await;
+        }
+
+        let children: HashSet<String> = self.watch_children().await?.into_iter().collect();
+        let removed: Vec<String> = self
+            .map
+            .keys()
+            .filter(|key| !children.contains(*key))
+            .cloned()
+            .collect();
+        for key in removed {
+            self.map.remove(&key);
+            self.pending.push_back(ZookeeperMapEvent::Removed { key });
+        }
+        for key in children {
+            if self.map.contains_key(&key) {
+                continue;
+            }
+            if let Some(value) = self.watch_data(&key).await? {
+                self.map.insert(key.clone(), value.clone());
+                self.pending
+                    .push_back(ZookeeperMapEvent::Added { key, value });
+            }
+        }
+        Ok(())
+    }
+
+    async fn on_data_changed(&mut self, key: String, event: zk::WatchedEvent) -> Result<()> {
+        self.watched.remove(&key);
+        if event.event_type == zk::EventType::Session {
+            return self.reset().await;
+        }
+        // Removed children are handled by the children watch.
+        if !self.map.contains_key(&key) {
+            return Ok(());
+        }
+
+        match self.watch_data(&key).await? {
+            Some(value) => {
+                if self.map.get(&key) != Some(&value) {
+                    self.map.insert(key.clone(), value.clone());
+                    self.pending
+                        .push_back(ZookeeperMapEvent::Updated { key, value });
+                }
+            }
+            None => {
+                self.map.remove(&key);
+                self.pending.push_back(ZookeeperMapEvent::Removed { key });
+            }
+        }
+        Ok(())
+    }
+
+    /// Fetch the whole map again after session lost.
+    async fn reset(&mut self) -> Result<()> {
+        self.client = self.adapter.get_connection().await?;
+        self.resync().await?;
+        self.pending.clear();
+        self.pending
+            .push_back(ZookeeperMapEvent::Reset(self.map.clone()));
+        Ok(())
+    }
+
+    async fn resync(&mut self) -> Result<()> {
+        self.data_watches = FuturesUnordered::new();
+        self.watched.clear();
+        self.map.clear();
+
+        for key in self.watch_children().await? {
+            if let Some(value) = self.watch_data(&key).await? {
+                self.map.insert(key, value);
+            }
+        }
+        Ok(())
+    }
+
+    /// List the children of prefix and watch for changes of them.
+    ///
+    /// A missing prefix is watched for creation instead.
+    async fn watch_children(&mut self) -> Result<Vec<String>> {
+        loop {
+            match self.client.get_and_watch_children(&self.prefix).await {
+                Ok((children, _, watcher)) => {
+                    self.children_watch = watcher.changed().boxed();
+                    return Ok(children);
+                }
+                Err(zk::Error::NoNode) => {}
+                Err(e) => return Err(parse_zookeeper_error(e)),
+            }
+
+            let (stat, watcher) = self
+                .client
+                .check_and_watch_stat(&self.prefix)
+                .await
+                .map_err(parse_zookeeper_error)?;
+            if stat.is_none() {
+                // Still missing, wait for it to be created.
+                self.children_watch = watcher.changed().boxed();
+                return Ok(Vec::new());
+            }
+            // Prefix has been created in between, list it again.
+        }
+    }
+
+    /// Read the value of child and watch for changes of it if not watched
+    /// yet, returns `None` if the child doesn't exist.
+    async fn watch_data(&mut self, key: &str) -> Result<Option<Vec<u8>>> {
+        let path = join_zookeeper_path(&self.prefix, key);
+        let data = if self.watched.contains(key) {
+            self.client.get_data(&path).await.map(|(data, _)| data)
+        } else {
+            self.client
+                .get_and_watch_data(&path)
+                .await
+                .map(|(data, _, watcher)| {
+                    let key = key.to_string();
+                    self.watched.insert(key.clone());
+                    self.data_watches
+                        .push(watcher.changed().map(|event| (key, event)).boxed());
+                    data
+                })
+        };
+
+        match data {
+            Ok(data) => self.adapter.decode_value(data).map(Some),
+            Err(zk::Error::NoNode) => Ok(None),
+            Err(e) => Err(parse_zookeeper_error(e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_watch_event() {
+        let cases = vec![
+            (None, None, None),
+            (
+                None,
+                Some((b"a".to_vec(), 0)),
+                Some(ZookeeperWatchEvent::Created {
+                    value: b"a".to_vec(),
+                }),
+            ),
+            (Some(0), None, Some(ZookeeperWatchEvent::Deleted)),
+            (Some(1), Some((b"a".to_vec(), 1)), None),
+            (
+                Some(1),
+                Some((b"b".to_vec(), 3)),
+                Some(ZookeeperWatchEvent::Changed {
+                    value: b"b".to_vec(),
+                }),
+            ),
+        ];
+
+        for (previous, current, expected) in cases {
+            assert_eq!(watch_event(previous, current), expected);
+        }
+    }
+}